@@ -1,94 +1,761 @@
-use std::io::{self, Read, BufReader};
-use std::collections::{HashMap, BinaryHeap};
+use std::io::{self, Read, BufReader, Write};
+use std::collections::{HashMap, HashSet, BinaryHeap};
 use std::cmp::Reverse;
+use std::borrow::Cow;
+use std::env;
+use std::fs::File;
+use std::process;
+
+// Every road keeps its own arcs, so parallel roads between the same pair
+// of nodes are distinct and can be banned independently. An undirected road
+// becomes two arcs sharing the same edge number.
+struct Arc {
+    from: usize,
+    to: usize,
+    d: u64,
+    profile: Option<Profile>,
+    edge: usize,
+}
+impl Arc {
+    fn cost(&self, t: u64) -> u64 {
+        match &self.profile {
+            Some(profile) => profile.at(t),
+            None => self.d,
+        }
+    }
+}
+
+// Weights, profile times and departure times are fixed point with MILLI
+// steps per unit, so fractional weights add up exactly and can't round into
+// false ties. Finer fractions are rounded to the nearest step.
+const MILLI: u64 = 1000;
+
+fn parse_fixed(s: &str) -> Option<u64> {
+    let (int, frac) = match s.find('.') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    };
+    if int.is_empty() || !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let round = frac.as_bytes().get(3).is_some_and(|&b| b >= b'5') as u64;
+    let frac: u64 = format!("{:0<3}", &frac[..frac.len().min(3)]).parse().unwrap();
+    int.parse::<u64>().ok()?.checked_mul(MILLI)?.checked_add(frac + round)
+}
+
+fn show_fixed(v: u64) -> String {
+    let (int, frac) = (v / MILLI, v % MILLI);
+    if frac == 0 {
+        int.to_string()
+    } else {
+        format!("{}.{}", int, format!("{:03}", frac).trim_end_matches('0'))
+    }
+}
+
+// Travel time as a piecewise linear function of the departure time, given
+// by (time, travel time) breakpoints and constant outside them. Slopes are
+// at least -1 so that leaving later never means arriving earlier.
+#[derive(Clone)]
+struct Profile(Vec<(u64, u64)>);
+impl Profile {
+    fn parse(s: &str) -> Option<Self> {
+        let mut points = Vec::new();
+        for point in s.split(';') {
+            let mut split = point.split(':');
+            let t = parse_fixed(split.next()?)?;
+            let w = parse_fixed(split.next()?)?;
+            if split.next().is_some() { return None; }
+            points.push((t, w));
+        }
+        for w in points.windows(2) {
+            let ((t0, w0), (t1, w1)) = (w[0], w[1]);
+            if t0 >= t1 || w0 > w1 + (t1 - t0) { return None; }
+        }
+        Some(Profile(points))
+    }
+    fn min(&self) -> u64 {
+        self.0.iter().map(|p| p.1).min().unwrap()
+    }
+    // rounding down keeps t + at(t) non-decreasing
+    fn at(&self, t: u64) -> u64 {
+        let points = &self.0;
+        if t <= points[0].0 {
+            return points[0].1;
+        }
+        for w in points.windows(2) {
+            let ((t0, w0), (t1, w1)) = (w[0], w[1]);
+            if t <= t1 {
+                let (dt, span) = ((t - t0) as u128, (t1 - t0) as u128);
+                return if w1 >= w0 {
+                    w0 + ((w1 - w0) as u128 * dt / span) as u64
+                } else {
+                    w0 - ((w0 - w1) as u128 * dt).div_ceil(span) as u64
+                };
+            }
+        }
+        points[points.len() - 1].1
+    }
+}
 
 struct Problem {
     n: usize,
-    neighbours: Vec<HashMap<usize, u64>>,
+    edges: usize,
+    arcs: Vec<Arc>,
+    neighbours: Vec<Vec<usize>>,
+    reverse: Vec<Vec<usize>>,
+    labels: Vec<String>,
+    ids: HashMap<String, usize>,
 }
 
 
+struct Elimination {
+    dist: Vec<Option<u64>>,
+    banned: Vec<bool>,
+}
+
+// key is the distance plus any lower bound on the rest of the way
 #[derive(Ord,PartialOrd,Eq,PartialEq)]
 struct QueueState {
+    key: u64,
     dist: u64,
     node: usize,
     prev: usize,
+    arc: usize,
 }
 
 impl Problem {
-    fn dijkstra(&self, from: usize) -> Vec<Option<usize>> {
+    fn new() -> Self {
+        Self {
+            n: 0,
+            edges: 0,
+            arcs: Vec::new(),
+            neighbours: Vec::new(),
+            reverse: Vec::new(),
+            labels: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+    // nodes are numbered densely in the order their labels first appear
+    fn node(&mut self, label: &str) -> usize {
+        if let Some(&idx) = self.ids.get(label) {
+            return idx;
+        }
+        let idx = self.n;
+        self.n += 1;
+        self.neighbours.push(Vec::new());
+        self.reverse.push(Vec::new());
+        self.labels.push(label.to_string());
+        self.ids.insert(label.to_string(), idx);
+        idx
+    }
+    fn lookup(&self, label: &str) -> usize {
+        match self.ids.get(label) {
+            Some(&idx) => idx,
+            None => fail(&format!("unknown node {}", label)),
+        }
+    }
+    fn add_arc(&mut self, from: usize, to: usize, d: u64, profile: Option<Profile>, edge: usize) {
+        let id = self.arcs.len();
+        self.arcs.push(Arc { from, to, d, profile, edge });
+        self.neighbours[from].push(id);
+        self.reverse[to].push(id);
+    }
+    // a profile replaces the fixed weight, which then only serves as the
+    // fastest possible travel time
+    fn add_edge(&mut self, a: usize, b: usize, d: u64, profile: Option<Profile>, directed: bool) {
+        let edge = self.edges;
+        self.edges += 1;
+        if !directed {
+            self.add_arc(a, b, d, profile.clone(), edge);
+            self.add_arc(b, a, d, profile, edge);
+        } else {
+            self.add_arc(a, b, d, profile, edge);
+        }
+    }
+    fn time_dependent(&self) -> bool {
+        self.arcs.iter().any(|arc| arc.profile.is_some())
+    }
+    // whether a time-dependent arc lies on some route from `from` to the
+    // destination the static distances `dist` lead to
+    fn timed_route(&self, from: usize, dist: &[Option<u64>]) -> bool {
+        let mut seen = vec![false; self.n];
+        let mut stack = vec![from];
+        seen[from] = true;
+        while let Some(node) = stack.pop() {
+            for id in self.neighbours[node].iter().cloned() {
+                let arc = &self.arcs[id];
+                if dist[arc.to].is_none() { continue; }
+                if arc.profile.is_some() { return true; }
+                if !seen[arc.to] {
+                    seen[arc.to] = true;
+                    stack.push(arc.to);
+                }
+            }
+        }
+        false
+    }
+    // the state of each reached node is its distance and the arc used to
+    // reach it, which is None for the start. Forward searches leave `from`
+    // at time `start` and follow the arc profiles, so the distance is then
+    // the arrival time; reversed searches use the fixed weights. With a
+    // target the search stops once it is settled, and static distances to
+    // it, which never exceed the profiles, turn the search into A* that
+    // skips nodes unable to reach it.
+    fn dijkstra<F>(&self, from: usize, reversed: bool, start: u64, target: Option<(usize, Option<&[Option<u64>]>)>, allowed: F) -> Vec<Option<(u64, Option<usize>)>>
+    where F: Fn(usize) -> bool
+    {
+        let graph = if reversed { &self.reverse } else { &self.neighbours };
+        let lower = |node: usize| match target {
+            Some((_, Some(dist))) => dist[node],
+            _ => Some(0),
+        };
         let mut state = vec![None; self.n];
         let mut queue = BinaryHeap::new();
         queue.push(Reverse(QueueState {
-            dist: 0,
+            key: start,
+            dist: start,
             node: from,
             prev: from,
+            arc: usize::MAX,
         }));
         while let Some(Reverse(n)) = queue.pop() {
             if state[n.node].is_some() { continue; }
-            state[n.node] = Some(n.prev);
-            for (neigh, d) in self.neighbours[n.node].iter().map(|(a,b)| (*a,*b)) {
-                if state[neigh].is_some() { continue; }
+            let arc = if n.arc == usize::MAX { None } else { Some(n.arc) };
+            state[n.node] = Some((n.dist, arc));
+            if target.is_some_and(|(to, _)| to == n.node) { break; }
+            for id in graph[n.node].iter().cloned() {
+                let arc = &self.arcs[id];
+                let neigh = if reversed { arc.from } else { arc.to };
+                if state[neigh].is_some() || !allowed(id) { continue; }
+                let h = match lower(neigh) {
+                    Some(h) => h,
+                    None => continue,
+                };
+                let d = if reversed { arc.d } else { arc.cost(n.dist) };
                 queue.push(Reverse(QueueState {
+                    key: n.dist + d + h,
                     dist: n.dist + d,
                     node: neigh,
                     prev: n.node,
+                    arc: id,
                 }));
             }
         }
         state
     }
-    fn eliminate(&mut self) {
-        for (n, state) in self.dijkstra(1).into_iter().enumerate() {
-            if let Some(state) = state {
-                self.neighbours[n].remove(&state);
+    // searching the reversed graph from the destination gives every node
+    // the arc it leaves by along a shortest path to the destination. With
+    // all_ties every arc that starts some shortest path is banned, not just
+    // the one the search happened to pick.
+    fn eliminate(&self, to: usize, all_ties: bool) -> Elimination {
+        let tree = self.dijkstra(to, true, 0, None, |_| true);
+        let dist: Vec<_> = tree.iter().map(|s| s.map(|(d, _)| d)).collect();
+        let mut banned = vec![false; self.arcs.len()];
+        if all_ties {
+            for (id, arc) in self.arcs.iter().enumerate() {
+                if arc.from == to { continue; }
+                if let (Some(a), Some(b)) = (dist[arc.from], dist[arc.to]) {
+                    if a == b + arc.d {
+                        banned[id] = true;
+                    }
+                }
             }
+        } else {
+            for state in tree.iter() {
+                if let Some((_, Some(id))) = *state {
+                    banned[id] = true;
+                }
+            }
+        }
+        Elimination {
+            dist,
+            banned,
+        }
+    }
+    // returns the arcs of the path in order
+    fn solve(&self, from: usize, to: usize, banned: &[bool]) -> Option<Vec<usize>> {
+        let res = self.dijkstra(from, false, 0, Some((to, None)), |id| !banned[id]);
+        self.trace(&res, from, to)
+    }
+    // Yen's algorithm over the pruned graph: every spur path avoids the
+    // banned arcs, the nodes of its root path and the next arcs of earlier
+    // paths sharing that root, so all paths found are loopless.
+    fn k_shortest(&self, from: usize, to: usize, banned: &[bool], k: usize) -> Vec<Vec<usize>> {
+        let mut found = Vec::new();
+        match self.solve(from, to, banned) {
+            Some(path) => found.push(path),
+            None => return found,
         }
+        let mut candidates = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        while found.len() < k {
+            let last: Vec<usize> = found[found.len() - 1].clone();
+            for i in 0..last.len() {
+                let root = &last[..i];
+                let spur = if i == 0 { from } else { self.arcs[last[i-1]].to };
+                let mut blocked = banned.to_vec();
+                for path in found.iter() {
+                    if path.len() > i && path[..i] == *root {
+                        blocked[path[i]] = true;
+                    }
+                }
+                let mut removed = vec![false; self.n];
+                for id in root.iter() {
+                    removed[self.arcs[*id].from] = true;
+                }
+                let res = self.dijkstra(spur, false, 0, Some((to, None)), |id| !blocked[id] && !removed[self.arcs[id].to]);
+                if let Some(spur_path) = self.trace(&res, spur, to) {
+                    let path: Vec<usize> = root.iter().chain(spur_path.iter()).cloned().collect();
+                    if seen.insert(path.clone()) {
+                        let cost: u64 = path.iter().map(|id| self.arcs[*id].d).sum();
+                        candidates.push(Reverse((cost, path)));
+                    }
+                }
+            }
+            match candidates.pop() {
+                Some(Reverse((_, path))) => found.push(path),
+                None => break,
+            }
+        }
+        found
     }
-    fn solve(&self) -> Option<Vec<usize>> {
-        let res = self.dijkstra(0);
-        let mut path = vec![1];
-        let mut i = 1;
-        while i != 0 {
-            let prev = res[i]?;
-            path.push(prev);
-            i = prev;
+    fn trace(&self, res: &[Option<(u64, Option<usize>)>], from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut path = Vec::new();
+        let mut i = to;
+        while i != from {
+            let id = res[i]?.1.unwrap();
+            path.push(id);
+            i = self.arcs[id].from;
         }
+        path.reverse();
         Some(path)
     }
+    // arcs that leave `node` at time `t` along an earliest arrival path to
+    // `to`, whose static distances are `lower`
+    fn first_hops(&self, node: usize, t: u64, to: usize, lower: &[Option<u64>], all_ties: bool) -> Vec<usize> {
+        let target = Some((to, Some(lower)));
+        let tree = self.dijkstra(node, false, t, target, |_| true);
+        let best = match tree[to] {
+            Some((arrival, _)) if node != to => arrival,
+            _ => return Vec::new(),
+        };
+        if all_ties {
+            return self.neighbours[node].iter().cloned().filter(|&id| {
+                let arc = &self.arcs[id];
+                let tree = self.dijkstra(arc.to, false, t + arc.cost(t), target, |_| true);
+                tree[to].map(|(arrival, _)| arrival) == Some(best)
+            }).collect();
+        }
+        let mut i = to;
+        loop {
+            let id = tree[i].unwrap().1.unwrap();
+            if self.arcs[id].from == node {
+                return vec![id];
+            }
+            i = self.arcs[id].from;
+        }
+    }
+    // With time-dependent arcs the next hop to ban depends on when a node is
+    // left, so it is recomputed for every node as it is settled at its
+    // earliest arrival time. The arcs banned along the way are returned
+    // with the path.
+    fn solve_at(&self, from: usize, to: usize, depart: u64, lower: &[Option<u64>], all_ties: bool) -> (Option<Vec<usize>>, Vec<bool>) {
+        let mut state = vec![None; self.n];
+        let mut banned_at = vec![false; self.arcs.len()];
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse(QueueState {
+            key: depart,
+            dist: depart,
+            node: from,
+            prev: from,
+            arc: usize::MAX,
+        }));
+        while let Some(Reverse(n)) = queue.pop() {
+            if state[n.node].is_some() { continue; }
+            let arc = if n.arc == usize::MAX { None } else { Some(n.arc) };
+            state[n.node] = Some((n.dist, arc));
+            if n.node == to { break; }
+            let banned = self.first_hops(n.node, n.dist, to, lower, all_ties);
+            for id in banned.iter().cloned() {
+                banned_at[id] = true;
+            }
+            for id in self.neighbours[n.node].iter().cloned() {
+                let arc = &self.arcs[id];
+                if state[arc.to].is_some() || banned.contains(&id) { continue; }
+                queue.push(Reverse(QueueState {
+                    key: n.dist + arc.cost(n.dist),
+                    dist: n.dist + arc.cost(n.dist),
+                    node: arc.to,
+                    prev: n.node,
+                    arc: id,
+                }));
+            }
+        }
+        (self.trace(&state, from, to), banned_at)
+    }
+    fn earliest_arrival(&self, from: usize, to: usize, depart: u64, lower: &[Option<u64>]) -> Option<u64> {
+        self.dijkstra(from, false, depart, Some((to, Some(lower))), |_| true)[to].map(|(arrival, _)| arrival)
+    }
 }
 
-fn main() {
-    let stdin = io::stdin();
-    let mut stdin = BufReader::new(stdin.lock());
-    let mut buf = String::new();
-    stdin.read_to_string(&mut buf).unwrap();
+#[derive(Copy,Clone,PartialEq,Default)]
+enum Output {
+    #[default]
+    Plain,
+    Report,
+    Json,
+}
+
+fn read_kattis(buf: &str, directed: bool) -> (Problem, Vec<(usize, usize)>) {
     let mut iter = buf.split_ascii_whitespace();
 
-    let n = iter.next().unwrap().parse().unwrap();
+    let n: usize = iter.next().unwrap().parse().unwrap();
     let m: usize = iter.next().unwrap().parse().unwrap();
-    let mut p = Problem {
-        n,
-        neighbours: vec![HashMap::new(); n],
-    };
+    let mut p = Problem::new();
+    for i in 0..n {
+        p.node(&i.to_string());
+    }
 
     for _ in 0..m {
         let a: usize = iter.next().unwrap().parse().unwrap();
         let b: usize = iter.next().unwrap().parse().unwrap();
-        let d = iter.next().unwrap().parse().unwrap();
-        p.neighbours[a].insert(b, d);
-        p.neighbours[b].insert(a, d);
+        let w = iter.next().unwrap();
+        let (d, profile) = parse_weight(w).unwrap_or_else(|| fail(&format!("bad weight {:?}", w)));
+        p.add_edge(a, b, d, profile, directed);
     }
 
-    p.eliminate();
-    match p.solve() {
-        Some(path) => {
+    let mut queries = Vec::new();
+    if let Some(q) = iter.next() {
+        for _ in 0..q.parse().unwrap() {
+            let mut node = || {
+                let label = iter.next().unwrap();
+                label.parse().ok().filter(|&i| i < n).unwrap_or_else(|| fail(&format!("unknown node {}", label)))
+            };
+            let (from, to) = (node(), node());
+            queries.push((from, to));
+        }
+    }
+    (p, queries)
+}
+
+// DIMACS shortest path format: "p sp n m", "a u v w" arcs and optionally
+// "q s t" point to point queries
+fn read_dimacs(buf: &str) -> (Problem, Vec<(usize, usize)>) {
+    let mut p = Problem::new();
+    let mut queries = Vec::new();
+    for line in buf.lines() {
+        let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+        match fields.as_slice() {
+            ["p", "sp", n, _] => for i in 1..=n.parse::<usize>().unwrap_or_else(|_| fail(&format!("bad node count in {:?}", line))) {
+                p.node(&i.to_string());
+            },
+            ["a", u, v, w] => {
+                let (u, v) = (p.node(u), p.node(v));
+                let (w, profile) = parse_weight(w).unwrap_or_else(|| fail(&format!("bad weight in {:?}", line)));
+                p.add_edge(u, v, w, profile, true);
+            }
+            ["q", s, t] => queries.push((s.to_string(), t.to_string())),
+            [] | ["c", ..] | ["p", ..] => {}
+            _ => fail(&format!("unexpected line {:?}", line)),
+        }
+    }
+    let queries = queries.iter().map(|(s, t)| (p.lookup(s), p.lookup(t))).collect();
+    (p, queries)
+}
+
+// one edge per line as from,to,weight; a header line naming the columns
+// is optional and lets the three columns appear anywhere in the row. A first
+// row is taken as the header when it names a from column.
+const FROM_COLUMNS: [&str; 4] = ["from", "source", "src", "u"];
+
+fn read_csv(buf: &str, directed: bool) -> Problem {
+    let mut p = Problem::new();
+    let mut cols = None;
+    for line in buf.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim().trim_matches('"')).collect();
+        let named = |names: &[&str]| fields.iter().position(|f| names.contains(&f.to_lowercase().as_str()));
+        let (a, b, w) = match cols {
+            Some(cols) => cols,
+            None if named(&FROM_COLUMNS).is_none() => (0, 1, 2),
+            None => {
+                let find = |names: &[&str]| named(names)
+                    .unwrap_or_else(|| fail(&format!("no {} column in {:?}", names[0], line)));
+                cols = Some((
+                    find(&FROM_COLUMNS),
+                    find(&["to", "target", "dst", "v"]),
+                    find(&["weight", "length", "cost", "time", "w"]),
+                ));
+                continue;
+            }
+        };
+        cols = Some((a, b, w));
+        if fields.len() <= a.max(b).max(w) {
+            fail(&format!("too few columns in {:?}", line));
+        }
+        let (u, v) = (p.node(fields[a]), p.node(fields[b]));
+        let (w, profile) = parse_weight(fields[w]).unwrap_or_else(|| fail(&format!("bad weight in {:?}", line)));
+        p.add_edge(u, v, w, profile, directed);
+    }
+    p
+}
+
+// A weight is either a number or a travel time profile written as
+// time:travel;time:travel;... Fractional weights, common in road extracts,
+// are kept to MILLI steps.
+fn parse_weight(s: &str) -> Option<(u64, Option<Profile>)> {
+    if s.contains(':') {
+        let profile = Profile::parse(s)?;
+        return Some((profile.min(), Some(profile)));
+    }
+    Some((parse_fixed(s)?, None))
+}
+
+// Draws every road once, except that roads with arcs banned by the
+// elimination or used by the detour are split into their arcs, the banned
+// ones dashed red and the detour bold blue.
+fn write_dot<W: Write>(out: &mut W, p: &Problem, from: usize, to: usize, banned: &[bool], path: Option<&[usize]>) -> io::Result<()> {
+    let mut on_path = vec![false; p.arcs.len()];
+    for id in path.unwrap_or(&[]).iter() {
+        on_path[*id] = true;
+    }
+    let node = |i: usize| format!("\"{}\"", p.labels[i].replace('\\', "\\\\").replace('"', "\\\""));
+
+    writeln!(out, "digraph detour_{}_{} {{", from, to)?;
+    for i in 0..p.n {
+        let style = if i == from || i == to { " [shape=doublecircle]" } else { "" };
+        writeln!(out, "    {}{};", node(i), style)?;
+    }
+    let mut start = 0;
+    while start < p.arcs.len() {
+        let edge = p.arcs[start].edge;
+        let end = start + p.arcs[start..].iter().take_while(|arc| arc.edge == edge).count();
+        if (start..end).all(|id| !on_path[id] && !banned[id]) {
+            let arc = &p.arcs[start];
+            let dir = if end - start > 1 { ", dir=none" } else { "" };
+            writeln!(out, "    {} -> {} [label={}{}];", node(arc.from), node(arc.to), show_fixed(arc.d), dir)?;
+        } else {
+            for id in start..end {
+                let arc = &p.arcs[id];
+                let style = if on_path[id] {
+                    ", color=blue, penwidth=2"
+                } else if banned[id] {
+                    ", color=red, style=dashed"
+                } else {
+                    ""
+                };
+                writeln!(out, "    {} -> {} [label={}{}];", node(arc.from), node(arc.to), show_fixed(arc.d), style)?;
+            }
+        }
+        start = end;
+    }
+    writeln!(out, "}}")
+}
+
+fn print_route(p: &Problem, from: usize, to: usize, depart: u64, path: Option<Vec<usize>>, shortest: Option<u64>, output: Output) {
+    let path = match path {
+        Some(path) => path,
+        None if output == Output::Json => {
+            println!("{{\"source\":{},\"destination\":{},\"path\":null,\"shortest\":{}}}",
+                json_id(&p.labels[from]), json_id(&p.labels[to]), json_opt(shortest));
+            return;
+        }
+        None => {
+            println!("impossible");
+            return;
+        }
+    };
+    let mut t = depart;
+    let hops: Vec<(&Arc, u64)> = path.iter().map(|&id| {
+        let arc = &p.arcs[id];
+        let d = arc.cost(t);
+        t += d;
+        (arc, d)
+    }).collect();
+    let path: Vec<usize> = Some(from).into_iter().chain(hops.iter().map(|(arc, _)| arc.to)).collect();
+    let cost = t - depart;
+    // the detour is found in the pruned graph, so a shortest path exists
+    let shortest = shortest.unwrap();
+    match output {
+        Output::Plain | Output::Report => {
             print!("{}", path.len());
-            for i in path.into_iter().rev() {
-                print!(" {}", i);
+            for i in path.iter() {
+                print!(" {}", p.labels[*i]);
             }
+            println!();
+            if output == Output::Report {
+                for (arc, d) in hops {
+                    println!("hop {} {} {} edge {}", p.labels[arc.from], p.labels[arc.to], show_fixed(d), arc.edge);
+                }
+                println!("cost {} shortest {} extra {}", show_fixed(cost), show_fixed(shortest), show_fixed(cost - shortest));
+            }
+        }
+        Output::Json => {
+            let path: Vec<String> = path.iter().map(|&i| json_id(&p.labels[i])).collect();
+            let hops: Vec<String> = hops.iter()
+                .map(|(arc, d)| format!("{{\"from\":{},\"to\":{},\"weight\":{},\"edge\":{}}}",
+                    json_id(&p.labels[arc.from]), json_id(&p.labels[arc.to]), show_fixed(*d), arc.edge))
+                .collect();
+            println!("{{\"source\":{},\"destination\":{},\"path\":[{}],\"hops\":[{}],\"cost\":{},\"shortest\":{},\"extra\":{}}}",
+                json_id(&p.labels[from]), json_id(&p.labels[to]), path.join(","), hops.join(","),
+                show_fixed(cost), show_fixed(shortest), show_fixed(cost - shortest));
+        }
+    }
+}
+
+// ids that read back as the same number, exact in a double, are written as
+// numbers and anything else as a string, so 007 stays "007"
+fn json_id(label: &str) -> String {
+    if label.parse::<u64>().is_ok_and(|n| n < 1 << 53 && n.to_string() == label) {
+        label.to_string()
+    } else {
+        let mut res = String::from("\"");
+        for c in label.chars() {
+            match c {
+                '"' | '\\' => { res.push('\\'); res.push(c); }
+                c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+                c => res.push(c),
+            }
+        }
+        res.push('"');
+        res
+    }
+}
+
+fn json_opt(v: Option<u64>) -> String {
+    match v {
+        Some(v) => show_fixed(v),
+        None => String::from("null"),
+    }
+}
+
+#[derive(Copy,Clone,PartialEq,Default)]
+enum Format {
+    #[default]
+    Kattis,
+    Dimacs,
+    Csv,
+}
+
+#[derive(Default)]
+struct Options {
+    directed: bool,
+    all_ties: bool,
+    output: Output,
+    format: Format,
+    queries: Vec<(String, String)>,
+    depart: u64,
+    dot: Option<String>,
+    k: Option<usize>,
+}
+
+fn usage() -> ! {
+    eprintln!("usage: detour [--format kattis|dimacs|csv] [--query FROM TO]... [--directed] [--all-ties] [--depart TIME] [--dot FILE] [--k K] [--report | --json] < input");
+    eprintln!("the edge list may be followed by q and q lines of source and destination");
+    eprintln!("routes that can use a time-dependent weight cost one search per node settled, more with --all-ties");
+    process::exit(2);
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("detour: {}", msg);
+    process::exit(1);
+}
+
+fn parse_args() -> Options {
+    let mut opts = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => opts.format = match args.next().as_deref() {
+                Some("kattis") => Format::Kattis,
+                Some("dimacs") => Format::Dimacs,
+                Some("csv") => Format::Csv,
+                _ => usage(),
+            },
+            "--query" => match (args.next(), args.next()) {
+                (Some(from), Some(to)) => opts.queries.push((from, to)),
+                _ => usage(),
+            },
+            "--depart" => opts.depart = args.next().and_then(|t| parse_fixed(&t)).unwrap_or_else(|| usage()),
+            "--dot" => opts.dot = Some(args.next().unwrap_or_else(|| usage())),
+            "--k" => opts.k = Some(args.next().and_then(|k| k.parse().ok()).filter(|&k| k > 0).unwrap_or_else(|| usage())),
+            "--directed" => opts.directed = true,
+            "--all-ties" => opts.all_ties = true,
+            "--report" => opts.output = Output::Report,
+            "--json" => opts.output = Output::Json,
+            _ => usage(),
+        }
+    }
+    opts
+}
+
+fn main() {
+    let opts = parse_args();
+
+    let stdin = io::stdin();
+    let mut stdin = BufReader::new(stdin.lock());
+    let mut buf = String::new();
+    stdin.read_to_string(&mut buf).unwrap();
+
+    let (p, mut queries) = match opts.format {
+        Format::Kattis => read_kattis(&buf, opts.directed),
+        Format::Dimacs => read_dimacs(&buf),
+        Format::Csv => (read_csv(&buf, opts.directed), Vec::new()),
+    };
+    for (from, to) in opts.queries.iter() {
+        queries.push((p.lookup(from), p.lookup(to)));
+    }
+    if queries.is_empty() {
+        if opts.format != Format::Kattis {
+            fail("no queries given");
+        }
+        queries.push((0, 1));
+    }
+
+    let mut dot = opts.dot.as_ref().map(|path| {
+        File::create(path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+    });
+
+    let time_dependent = p.time_dependent();
+    if time_dependent && opts.k.is_some() {
+        fail("--k is not supported with time-dependent weights");
+    }
+    let depart = if time_dependent { opts.depart } else { 0 };
+    let mut eliminated = HashMap::new();
+    for (q, (from, to)) in queries.into_iter().enumerate() {
+        if let Some(k) = opts.k {
+            let elim = eliminated.entry(to).or_insert_with(|| p.eliminate(to, opts.all_ties));
+            let paths = p.k_shortest(from, to, &elim.banned, k);
+            if q > 0 && opts.output != Output::Json {
+                println!();
+            }
+            if let Some(out) = dot.as_mut() {
+                write_dot(out, &p, from, to, &elim.banned, paths.first().map(|p| &p[..]))
+                    .unwrap_or_else(|e| fail(&format!("{}: {}", opts.dot.as_ref().unwrap(), e)));
+            }
+            if paths.is_empty() {
+                print_route(&p, from, to, 0, None, elim.dist[from], opts.output);
+            }
+            for path in paths {
+                print_route(&p, from, to, 0, Some(path), elim.dist[from], opts.output);
+            }
+            continue;
+        }
+
+        // the costly search is only needed when a profile arc can be used
+        let elim = eliminated.entry(to).or_insert_with(|| p.eliminate(to, opts.all_ties));
+        let (path, shortest, banned) = if time_dependent && p.timed_route(from, &elim.dist) {
+            let (path, banned) = p.solve_at(from, to, depart, &elim.dist, opts.all_ties);
+            let shortest = p.earliest_arrival(from, to, depart, &elim.dist).map(|t| t - depart);
+            (path, shortest, Cow::Owned(banned))
+        } else {
+            (p.solve(from, to, &elim.banned), elim.dist[from], Cow::Borrowed(&elim.banned[..]))
+        };
+        if let Some(out) = dot.as_mut() {
+            write_dot(out, &p, from, to, &banned, path.as_deref())
+                .unwrap_or_else(|e| fail(&format!("{}: {}", opts.dot.as_ref().unwrap(), e)));
         }
-        None => print!("impossible"),
+        print_route(&p, from, to, depart, path, shortest, opts.output);
     }
-    println!();
 }
@@ -1,9 +1,727 @@
 use std::io::{self, BufRead, BufReader};
+use std::collections::{BinaryHeap, HashMap};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+use std::env;
+use std::process;
 
-fn parse_time(t: &str) -> usize {
+const DAY: usize = 24 * 60;
+
+// minutes since midnight
+fn parse_time(t: &str) -> Option<usize> {
+    if t.len() != 4 || !t.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
     let h: usize = t[0..2].parse().unwrap();
     let m: usize = t[2..4].parse().unwrap();
-    h * 60 + m - 9 * 60
+    if h >= 24 || m >= 60 {
+        return None;
+    }
+    Some(h * 60 + m)
+}
+
+// The working hours as minute offsets from their start. A window ending at
+// or before its start runs past midnight, one ending at its start is a whole
+// day.
+#[derive(Copy,Clone)]
+struct Window {
+    start: usize,
+    len: usize,
+}
+
+impl Window {
+    fn parse(s: &str) -> Option<Self> {
+        let mut split = s.splitn(2, '-');
+        let start = parse_time(split.next()?)?;
+        let end = parse_time(split.next()?)?;
+        let len = (end + DAY - start - 1) % DAY + 1;
+        Some(Window { start, len })
+    }
+
+    // An end time right at the start of the window is the end of a whole
+    // day, while a start time there is the beginning.
+    fn offset(&self, t: &str, end: bool) -> Option<usize> {
+        let t = parse_time(t)?;
+        let off = if end {
+            (t + DAY - self.start - 1) % DAY + 1
+        } else {
+            (t + DAY - self.start) % DAY
+        };
+        if off > self.len || (!end && off == self.len) {
+            return None;
+        }
+        Some(off)
+    }
+
+}
+
+// local minutes since the epoch
+fn format_time(t: i64, with_date: bool) -> String {
+    let minutes = t.rem_euclid(DAY as i64);
+    let hhmm = format!("{:04}", (minutes / 60) * 100 + minutes % 60);
+    if !with_date {
+        return hhmm;
+    }
+    let (y, m, d) = civil_from_days(t.div_euclid(DAY as i64));
+    format!("{:04}-{:02}-{:02} {}", y, m, d, hhmm)
+}
+
+// YYYY-MM-DDTHHMM or YYYYMMDDTHHMM, as local minutes since the epoch
+fn parse_stamp(s: &str) -> Option<i64> {
+    let t = s.find('T')?;
+    Some(parse_date(&s[..t])? * DAY as i64 + parse_time(&s[t + 1..])? as i64)
+}
+
+enum Mode {
+    Earliest,
+    Free,
+    Starts,
+    Best(usize),
+    MaxOptional,
+}
+
+#[derive(Copy,Clone)]
+enum Prefer {
+    Earliest,
+    Latest,
+    Lunch,
+    Buffer,
+}
+
+struct Options {
+    hours: Window,
+    mode: Mode,
+    prefer: Prefer,
+    lunch: usize,
+    optional: Vec<String>,
+    quorum: usize,
+    date: Option<i64>,
+    to: Option<i64>,
+    weekdays: bool,
+    holidays: Vec<i64>,
+    zone: i64,
+    ics: Vec<(String, String)>,
+    tz: Vec<(String, i64)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            hours: Window { start: 9 * 60, len: 8 * 60 },
+            mode: Mode::Earliest,
+            prefer: Prefer::Earliest,
+            lunch: 12 * 60,
+            optional: Vec::new(),
+            quorum: 0,
+            date: None,
+            to: None,
+            weekdays: false,
+            holidays: Vec::new(),
+            zone: 0,
+            ics: Vec::new(),
+            tz: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    fn set(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            "hours" => self.hours = Window::parse(value)?,
+            "lunch" => self.lunch = parse_time(value)?,
+            "optional" => self.optional.extend(value.split(',').map(String::from)),
+            "quorum" => self.quorum = value.parse().ok()?,
+            "date" => self.date = Some(parse_date(value)?),
+            "to" => self.to = Some(parse_date(value)?),
+            "holidays" => for d in value.split(',') {
+                self.holidays.push(parse_date(d)?);
+            },
+            "zone" => self.zone = parse_offset(value)?,
+            "ics" => {
+                let (person, path) = match value.find('=') {
+                    Some(i) => (&value[..i], &value[i + 1..]),
+                    None => (Path::new(value).file_stem()?.to_str()?, value),
+                };
+                self.ics.push((person.to_string(), path.to_string()));
+            }
+            "tz" => {
+                let i = value.rfind('=')?;
+                self.tz.push((value[..i].to_string(), parse_offset(&value[i + 1..])?));
+            }
+            "prefer" => self.prefer = match value {
+                "earliest" => Prefer::Earliest,
+                "latest" => Prefer::Latest,
+                "lunch" => Prefer::Lunch,
+                "buffer" => Prefer::Buffer,
+                _ => return None,
+            },
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: schedcon [--hours HHMM-HHMM] [--free | --starts | --best K | --max-optional] [--prefer earliest|latest|lunch|buffer] [--lunch HHMM] [--optional PERSON[,PERSON...]]... [--quorum N] < input");
+    eprintln!("the first line may be a header like: hours=2200-0600 prefer=lunch optional=3,4 quorum=1");
+    eprintln!("       [--date YYYY-MM-DD [--to YYYY-MM-DD] [--weekdays] [--holidays DATE[,DATE...]]...]");
+    eprintln!("       [--zone +HHMM] [--ics [PERSON=]FILE.ics]... [--tz TZID=+HHMM]...");
+    eprintln!("people not listed as optional are required");
+    eprintln!("calendar events on the dates are added to the entries, a calendar's person defaults to its file name");
+    eprintln!("entries are HHMM on the first date or YYYY-MM-DDTHHMM, and times are printed with dates when --to is given");
+    eprintln!("HHMM entries must lie within the working hours and dated ones must overlap them, calendar events are clipped to them");
+    process::exit(2);
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("schedcon: {}", msg);
+    process::exit(1);
+}
+
+// the command line overrides the header
+fn parse_args(header: &str) -> Options {
+    let mut opts = Options::default();
+    for item in header.split_whitespace() {
+        let mut split = item.splitn(2, '=');
+        let (key, value) = (split.next().unwrap(), split.next().unwrap_or_else(|| usage()));
+        opts.set(key, value).unwrap_or_else(|| usage());
+    }
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--free" => opts.mode = Mode::Free,
+            "--starts" => opts.mode = Mode::Starts,
+            "--best" => opts.mode = Mode::Best(args.next().and_then(|k| k.parse().ok()).unwrap_or_else(|| usage())),
+            "--max-optional" => opts.mode = Mode::MaxOptional,
+            "--weekdays" => opts.weekdays = true,
+            "--hours" | "--prefer" | "--lunch" | "--optional" | "--quorum"
+            | "--date" | "--to" | "--holidays" | "--zone" | "--ics" | "--tz" => {
+                let value = args.next().unwrap_or_else(|| usage());
+                opts.set(&arg[2..], &value).unwrap_or_else(|| usage());
+            }
+            _ => usage(),
+        }
+    }
+    opts
+}
+
+// Sorts intervals and joins those that overlap or touch.
+fn merge(mut intervals: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    intervals.sort();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+    for (s, e) in intervals {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    merged
+}
+
+// the maximal free runs of the working segments, both lists merged
+fn free_runs(segments: &[(i64, i64)], busy: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut runs = Vec::new();
+    let mut b = 0;
+    for &(mut s, e) in segments.iter() {
+        while b < busy.len() && busy[b].1 <= s {
+            b += 1;
+        }
+        let mut i = b;
+        while i < busy.len() && busy[i].0 < e {
+            if busy[i].0 > s {
+                runs.push((s, busy[i].0));
+            }
+            s = s.max(busy[i].1);
+            i += 1;
+        }
+        if s < e {
+            runs.push((s, e));
+        }
+    }
+    runs
+}
+
+// Starts from `from` up to but not including `to` that all lie in the free
+// run `run` and have `count` optional people free for the whole meeting.
+struct Piece {
+    from: i64,
+    to: i64,
+    run: (i64, i64),
+    count: usize,
+}
+
+// Splits the starts of the required free runs wherever the number of
+// optional people able to come changes, keeping those reaching the quorum.
+fn feasible_starts(runs: &[(i64, i64)], optional: &[Vec<(i64, i64)>], duration: i64, quorum: usize) -> Vec<Piece> {
+    let mut events = Vec::new();
+    for free in optional.iter() {
+        for &(s, e) in free.iter().filter(|&&(s, e)| e - s >= duration) {
+            events.push((s, 1i64));
+            events.push((e - duration + 1, -1));
+        }
+    }
+    events.sort();
+
+    let mut pieces = Vec::new();
+    let mut ev = 0;
+    let mut count = 0;
+    for &(s, e) in runs.iter().filter(|&&(s, e)| e - s >= duration) {
+        let (mut from, end) = (s, e - duration + 1);
+        while from < end {
+            while ev < events.len() && events[ev].0 <= from {
+                count += events[ev].1;
+                ev += 1;
+            }
+            let to = events.get(ev).map_or(end, |&(t, _)| t.min(end));
+            if count as usize >= quorum {
+                pieces.push(Piece { from, to, run: (s, e), count: count as usize });
+            }
+            from = to;
+        }
+    }
+    pieces
+}
+
+// Splits pieces where a working day starting at `origin` past midnight
+// begins. Whole-day hours merge the days into one run, and a piece spanning
+// two of them would see two lunches.
+fn split_days(pieces: &[Piece], origin: i64) -> Vec<Piece> {
+    let day = DAY as i64;
+    let mut split = Vec::with_capacity(pieces.len());
+    for p in pieces.iter() {
+        let mut from = p.from;
+        while from < p.to {
+            let to = p.to.min(origin + ((from - origin).div_euclid(day) + 1) * day);
+            split.push(Piece { from, to, run: p.run, count: p.count });
+            from = to;
+        }
+    }
+    split
+}
+
+// The k best starts by the preference, earlier starts breaking ties. Lunch
+// compares the meeting's midpoint with that day's lunch, buffer maximises
+// the smaller of the free minutes before and after the meeting. Every key
+// is convex over a piece within one day, so each piece is walked outwards
+// from its best start and the pieces are merged through a heap.
+fn best_starts(pieces: &[Piece], duration: i64, prefer: Prefer, lunch: &dyn Fn(i64) -> i64, k: usize) -> Vec<i64> {
+    let key = |p: &Piece, i: i64| match prefer {
+        Prefer::Earliest => i,
+        Prefer::Latest => -i,
+        Prefer::Lunch => (2 * i + duration - 2 * lunch(p.from)).abs(),
+        Prefer::Buffer => -(i - p.run.0).min(p.run.1 - i - duration),
+    };
+    let mut heap = BinaryHeap::new();
+    for (n, p) in pieces.iter().enumerate() {
+        let guess = match prefer {
+            Prefer::Earliest => p.from,
+            Prefer::Latest => p.to - 1,
+            Prefer::Lunch => (2 * lunch(p.from) - duration).div_euclid(2),
+            Prefer::Buffer => (p.run.0 + p.run.1 - duration).div_euclid(2),
+        };
+        let guess = guess.max(p.from).min(p.to - 1);
+        let best = (guess - 1..=guess + 1)
+            .filter(|&i| i >= p.from && i < p.to)
+            .min_by_key(|&i| (key(p, i), i))
+            .unwrap();
+        heap.push(Reverse((key(p, best), best, n, 0)));
+    }
+
+    // the direction a start was reached in, 0 for a piece's best start
+    let mut best = Vec::new();
+    while let Some(Reverse((_, i, n, dir))) = heap.pop() {
+        if best.len() == k { break; }
+        best.push(i);
+        let p = &pieces[n];
+        for &d in &[-1, 1] {
+            if (dir == 0 || dir == d) && i + d >= p.from && i + d < p.to {
+                heap.push(Reverse((key(p, i + d), i + d, n, d)));
+            }
+        }
+    }
+    best
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
+
+// 0 is Monday
+fn weekday(days: i64) -> i64 {
+    (days + 3).rem_euclid(7)
+}
+
+// YYYYMMDD, or YYYY-MM-DD on the command line
+fn parse_date(s: &str) -> Option<i64> {
+    let s = s.replace('-', "");
+    if s.len() != 8 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (y, m, d) = (s[0..4].parse().ok()?, s[4..6].parse().ok()?, s[6..8].parse().ok()?);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    Some(days_from_civil(y, m, d))
+}
+
+// +HHMM or -HHMM east of UTC, in minutes
+fn parse_offset(s: &str) -> Option<i64> {
+    let sign = match s.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let t = s.get(1..)?;
+    let m = parse_time(t).or_else(|| if t == "2400" { Some(DAY) } else { None })?;
+    Some(sign * m as i64)
+}
+
+// An iCalendar DATE or DATE-TIME as minutes since the epoch in whatever
+// zone it was written in, and whether it was UTC or a whole day.
+#[derive(Copy,Clone)]
+struct IcsTime {
+    minutes: i64,
+    utc: bool,
+    date: bool,
+}
+
+fn parse_ics_time(s: &str) -> Option<IcsTime> {
+    let days = parse_date(s.get(0..8)?)?;
+    if s.len() == 8 {
+        return Some(IcsTime { minutes: days * DAY as i64, utc: false, date: true });
+    }
+    let t = s.get(8..)?;
+    let (t, utc) = match t.strip_suffix('Z') {
+        Some(t) => (t, true),
+        None => (t, false),
+    };
+    if t.len() != 7 || !t.starts_with('T') {
+        return None;
+    }
+    let minutes = parse_time(&t[1..5])? as i64;
+    Some(IcsTime { minutes: days * DAY as i64 + minutes, utc, date: false })
+}
+
+// One STANDARD or DAYLIGHT part of a VTIMEZONE, in effect from its onset.
+// The onset recurs yearly on the nth weekday of a month when it has a rule,
+// negative n counting from the end of the month.
+struct Observance {
+    start: i64,
+    offset: i64,
+    rule: Option<(i64, i64, i64)>,
+}
+
+struct Zone(Vec<Observance>);
+
+impl Zone {
+    fn onset(obs: &Observance, year: i64) -> i64 {
+        let (month, nth, day) = match obs.rule {
+            Some(rule) => rule,
+            None => return obs.start,
+        };
+        let time = obs.start.rem_euclid(DAY as i64);
+        let days = if nth > 0 {
+            let first = days_from_civil(year, month, 1);
+            first + (day - weekday(first)).rem_euclid(7) + 7 * (nth - 1)
+        } else {
+            let (y, m) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+            let last = days_from_civil(y, m, 1) - 1;
+            last - (weekday(last) - day).rem_euclid(7) - 7 * (-nth - 1)
+        };
+        days * DAY as i64 + time
+    }
+
+    // the offset of the observance that most recently began at a local time
+    fn offset_at(&self, local: i64) -> i64 {
+        let (year, _, _) = civil_from_days(local.div_euclid(DAY as i64));
+        let mut best: Option<(i64, i64)> = None;
+        for obs in self.0.iter() {
+            for &y in &[year - 1, year] {
+                let onset = Zone::onset(obs, y);
+                if onset <= local && onset >= obs.start && best.is_none_or(|(b, _)| onset > b) {
+                    best = Some((onset, obs.offset));
+                }
+            }
+        }
+        best.or_else(|| self.0.first().map(|obs| (0, obs.offset))).map_or(0, |(_, offset)| offset)
+    }
+}
+
+// NAME;PARAM=VALUE;...:VALUE, on unfolded lines
+struct Property<'a> {
+    name: &'a str,
+    params: Vec<(&'a str, &'a str)>,
+    value: &'a str,
+}
+
+impl<'a> Property<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let colon = line.find(':')?;
+        let mut head = line[..colon].split(';');
+        let name = head.next()?;
+        let params = head.filter_map(|p| {
+            let mut split = p.splitn(2, '=');
+            Some((split.next()?, split.next()?.trim_matches('"')))
+        }).collect();
+        Some(Property { name, params, value: &line[colon + 1..] })
+    }
+    fn param(&self, key: &str) -> Option<&'a str> {
+        self.params.iter().find(|(k, _)| *k == key).map(|&(_, v)| v)
+    }
+}
+
+fn parse_byday(s: &str) -> Option<(i64, i64)> {
+    let days = ["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+    let split = s.len().checked_sub(2)?;
+    let day = days.iter().position(|&d| d == &s[split..])? as i64;
+    let nth = if split == 0 { 0 } else { s[..split].trim_start_matches('+').parse().ok()? };
+    Some((nth, day))
+}
+
+// PnW, PnDTnHnM and the like, in minutes
+fn parse_duration(s: &str) -> Option<i64> {
+    let (sign, s) = match s.strip_prefix('-') {
+        Some(s) => (-1, s),
+        None => (1, s.trim_start_matches('+')),
+    };
+    let mut total = 0;
+    let mut num = String::new();
+    let mut time = false;
+    for c in s.strip_prefix('P')?.chars() {
+        match c {
+            '0'..='9' => num.push(c),
+            'T' => time = true,
+            _ => {
+                let n: i64 = num.parse().ok()?;
+                num.clear();
+                total += n * match (c, time) {
+                    ('W', false) => 7 * DAY as i64,
+                    ('D', false) => DAY as i64,
+                    ('H', true) => 60,
+                    ('M', true) => 1,
+                    ('S', true) => 0,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(sign * total)
+}
+
+// Reads the busy times of a calendar as UTC minutes since the epoch,
+// expanding recurrences until `until`. Floating times and whole days are in
+// the schedule's zone, and TZIDs come from the file's VTIMEZONEs or the
+// given fixed offsets. Transparent and cancelled events don't block anything,
+// and events recurring in ways not understood only block their first start.
+fn read_ics(text: &str, zone: i64, offsets: &[(String, i64)], until: i64, warnings: &mut Vec<String>) -> Result<Vec<(i64, i64)>, String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    let props: Vec<Property> = lines.iter().filter_map(|l| Property::parse(l)).collect();
+
+    let mut zones: HashMap<&str, Zone> = HashMap::new();
+    for (name, offset) in offsets.iter() {
+        zones.insert(name, Zone(vec![Observance { start: i64::MIN / 2, offset: *offset, rule: None }]));
+    }
+    let mut tzid = None;
+    let mut obs: Option<Observance> = None;
+    for p in props.iter() {
+        match (p.name, p.value) {
+            ("BEGIN", "VTIMEZONE") => tzid = None,
+            ("TZID", v) => tzid = Some(v),
+            ("BEGIN", "STANDARD") | ("BEGIN", "DAYLIGHT") => obs = Some(Observance { start: 0, offset: 0, rule: None }),
+            ("DTSTART", v) if obs.is_some() => obs.as_mut().unwrap().start = parse_ics_time(v).ok_or("bad DTSTART")?.minutes,
+            ("TZOFFSETTO", v) if obs.is_some() => obs.as_mut().unwrap().offset = parse_offset(v).ok_or("bad TZOFFSETTO")?,
+            ("RRULE", v) if obs.is_some() => {
+                let rule: HashMap<&str, &str> = v.split(';').filter_map(|r| {
+                    let mut split = r.splitn(2, '=');
+                    Some((split.next()?, split.next()?))
+                }).collect();
+                let month = rule.get("BYMONTH").and_then(|m| m.parse().ok());
+                let day = rule.get("BYDAY").and_then(|d| parse_byday(d));
+                if let (Some(month), Some((nth, day))) = (month, day) {
+                    obs.as_mut().unwrap().rule = Some((month, if nth == 0 { 1 } else { nth }, day));
+                }
+            }
+            ("END", "STANDARD") | ("END", "DAYLIGHT") => {
+                let zone = zones.entry(tzid.ok_or("observance outside VTIMEZONE")?).or_insert(Zone(Vec::new()));
+                zone.0.extend(obs.take());
+            }
+            _ => {}
+        }
+    }
+
+    let offset = |t: &IcsTime, tzid: Option<&str>| -> Result<i64, String> {
+        Ok(match tzid {
+            _ if t.utc => 0,
+            Some(id) if !t.date => zones.get(id).ok_or_else(|| format!("unknown TZID {}", id))?.offset_at(t.minutes),
+            _ => zone,
+        })
+    };
+    let to_utc = |p: &Property| -> Result<(i64, bool), String> {
+        let t = parse_ics_time(p.value).ok_or_else(|| format!("bad {} {}", p.name, p.value))?;
+        Ok((t.minutes - offset(&t, p.param("TZID"))?, t.date))
+    };
+
+    // properties of nested components such as VALARM belong to those
+    let mut events = Vec::new();
+    let mut event: Option<Vec<&Property>> = None;
+    let mut nested = 0;
+    for p in props.iter() {
+        match (p.name, p.value) {
+            ("BEGIN", _) if event.is_some() => nested += 1,
+            ("END", _) if nested > 0 => nested -= 1,
+            _ if nested > 0 => {}
+            ("BEGIN", "VEVENT") => event = Some(Vec::new()),
+            ("END", "VEVENT") => events.push(event.take().ok_or("END without BEGIN")?),
+            _ => {
+                if let Some(event) = event.as_mut() {
+                    event.push(p);
+                }
+            }
+        }
+    }
+
+    // an instance moved or cancelled on its own replaces that start of its series
+    let mut moved = Vec::new();
+    for event in events.iter() {
+        let uid = event.iter().find(|p| p.name == "UID").map(|p| p.value);
+        if let (Some(uid), Some(p)) = (uid, event.iter().find(|p| p.name == "RECURRENCE-ID")) {
+            moved.push((uid, to_utc(p)?.0));
+        }
+    }
+
+    let mut busy = Vec::new();
+    for event in events.iter() {
+        let get = |name: &str| event.iter().find(|p| p.name == name);
+        if get("TRANSP").is_some_and(|p| p.value == "TRANSPARENT")
+            || get("STATUS").is_some_and(|p| p.value == "CANCELLED") {
+            continue;
+        }
+        let dtstart = get("DTSTART").ok_or("VEVENT without DTSTART")?;
+        let (start, date) = to_utc(dtstart)?;
+        let end = match (get("DTEND"), get("DURATION")) {
+            (Some(p), _) => to_utc(p)?.0,
+            (None, Some(p)) => start + parse_duration(p.value).ok_or("bad DURATION")?,
+            (None, None) if date => start + DAY as i64,
+            (None, None) => start,
+        };
+        let mut exdates = Vec::new();
+        for p in event.iter().filter(|p| p.name == "EXDATE") {
+            for v in p.value.split(',') {
+                let p = Property { name: p.name, params: p.params.clone(), value: v };
+                exdates.push(to_utc(&p)?.0);
+            }
+        }
+        if get("RECURRENCE-ID").is_none() {
+            if let Some(uid) = get("UID") {
+                exdates.extend(moved.iter().filter(|m| m.0 == uid.value).map(|m| m.1));
+            }
+        }
+        let local = parse_ics_time(dtstart.value).unwrap();
+        let rebase = |minutes: i64| -> Result<i64, String> {
+            let t = IcsTime { minutes, ..local };
+            Ok(minutes - offset(&t, dtstart.param("TZID"))?)
+        };
+        let starts = match get("RRULE") {
+            Some(rrule) => expand(rrule.value, local.minutes, until, &rebase)?.unwrap_or_else(|| {
+                warnings.push(format!("unsupported RRULE {}, only its first occurrence is busy", rrule.value));
+                vec![start]
+            }),
+            None => vec![start],
+        };
+        for s in starts {
+            if !exdates.contains(&s) && end > start {
+                busy.push((s, s + end - start));
+            }
+        }
+    }
+    Ok(busy)
+}
+
+// The UTC starts of a DAILY or WEEKLY RRULE, stepping in the event's local
+// time so that a recurring meeting keeps its wall clock time over DST. Other
+// frequencies give None.
+fn expand(rrule: &str, first: i64, until: i64, rebase: &dyn Fn(i64) -> Result<i64, String>) -> Result<Option<Vec<i64>>, String> {
+    let mut freq = None;
+    let mut interval = 1;
+    let mut count = None;
+    let mut last = None;
+    let mut byday = Vec::new();
+    for part in rrule.split(';') {
+        let mut split = part.splitn(2, '=');
+        let (key, value) = (split.next().unwrap(), split.next().unwrap_or(""));
+        match key {
+            "FREQ" => freq = Some(value),
+            "INTERVAL" => interval = value.parse().map_err(|_| "bad INTERVAL")?,
+            "COUNT" => count = Some(value.parse::<usize>().map_err(|_| "bad COUNT")?),
+            "UNTIL" => {
+                let t = parse_ics_time(value).ok_or("bad UNTIL")?;
+                last = Some(if t.utc { t.minutes } else { rebase(t.minutes)? });
+            }
+            "BYDAY" => for d in value.split(',') {
+                byday.push(parse_byday(d).ok_or("bad BYDAY")?.1);
+            },
+            _ => {}
+        }
+    }
+    if interval < 1 {
+        return Err(String::from("bad INTERVAL"));
+    }
+    let day = DAY as i64;
+    let (step, offsets) = match freq {
+        Some("DAILY") => (interval * day, vec![0]),
+        Some("WEEKLY") => {
+            let first_day = weekday(first.div_euclid(day));
+            if byday.is_empty() {
+                byday.push(first_day);
+            }
+            byday.sort();
+            byday.dedup();
+            (interval * 7 * day, byday.iter().map(|&d| (d - first_day) * day).collect())
+        }
+        _ => return Ok(None),
+    };
+
+    let mut starts = Vec::new();
+    let mut made = 0;
+    let mut base = first;
+    loop {
+        for &off in offsets.iter() {
+            let local = base + off;
+            if local < first { continue; }
+            let utc = rebase(local)?;
+            if count.is_some_and(|c| made >= c) || last.is_some_and(|l| utc > l) || utc >= until {
+                return Ok(Some(starts));
+            }
+            made += 1;
+            starts.push(utc);
+        }
+        base += step;
+    }
 }
 
 fn main() {
@@ -12,50 +730,127 @@ fn main() {
 
     let mut buf = String::new();
     stdin.read_line(&mut buf).unwrap();
+    let mut header = String::new();
+    if buf.contains('=') {
+        header = buf.clone();
+        buf.clear();
+        stdin.read_line(&mut buf).unwrap();
+    }
+    let opts = parse_args(&header);
+    let hours = opts.hours;
     let mut split = buf.split_whitespace();
 
     let _ = split.next().unwrap();
     let cal_entries = split.next().unwrap().parse().unwrap();
-    let meeting_duration = split.next().unwrap().parse().unwrap();
+    let duration: usize = split.next().unwrap().parse().unwrap();
 
-    let mut ps = vec![0i32; 1 + (17 - 9 + 1) * 60];
+    // The working hours of every included day as local minutes since the
+    // epoch. Without a date the single day is the epoch's, which is never
+    // printed.
+    let first = opts.date.unwrap_or(0);
+    let last = opts.to.unwrap_or(first);
+    if last < first {
+        fail("--to is before --date");
+    }
+    let day = DAY as i64;
+    let segments = merge((first..=last)
+        .filter(|&d| (!opts.weekdays || weekday(d) < 5) && !opts.holidays.contains(&d))
+        .map(|d| (d * day + hours.start as i64, d * day + (hours.start + hours.len) as i64))
+        .collect());
+    let with_date = opts.to.is_some();
+    let until = last * day + (hours.start + hours.len) as i64;
+
+    // busy intervals of the required people together, and of each optional
+    // one, of which free_runs only sees the parts within the segments
+    let mut required = Vec::new();
+    let mut optional = vec![Vec::new(); opts.optional.len()];
+    let optional_idx: HashMap<&str, usize> = opts.optional.iter().enumerate().map(|(i, p)| (p.as_str(), i)).collect();
+    let mut add = |person: &str, s: i64, e: i64| {
+        match optional_idx.get(person) {
+            Some(&i) => optional[i].push((s, e)),
+            None => required.push((s, e)),
+        }
+    };
 
     for _ in 0..cal_entries {
         let mut buf = String::new();
         stdin.read_line(&mut buf).unwrap();
         let mut split = buf.split_whitespace();
 
-        let _ = split.next().unwrap();
-        let start = parse_time(split.next().unwrap());
-        let end = parse_time(split.next().unwrap());
-
-        ps[start] += 1;
-        ps[end] -= 1;
-
-        assert!(start < end);
+        let person = split.next().unwrap();
+        let (start, end) = (split.next().unwrap(), split.next().unwrap());
+        let entry = match (parse_stamp(start), parse_stamp(end)) {
+            (Some(s), Some(e)) if s < e => {
+                if !segments.iter().any(|&(from, to)| s < to && from < e) {
+                    fail(&format!("entry {}-{} is not within the working hours", start, end));
+                }
+                (s, e)
+            }
+            (None, None) => match (hours.offset(start, false), hours.offset(end, true)) {
+                (Some(s), Some(e)) if s < e => (first * day + (hours.start + s) as i64, first * day + (hours.start + e) as i64),
+                _ => fail(&format!("entry {}-{} is not within the working hours", start, end)),
+            },
+            _ => fail(&format!("entry {}-{} is not a time range", start, end)),
+        };
+        add(person, entry.0, entry.1);
     }
 
-    let mut sum = 0;
-    for (i, ptr) in ps.iter_mut().enumerate() {
-        sum += *ptr;
-        *ptr = sum;
+    if !opts.ics.is_empty() {
+        if opts.date.is_none() {
+            fail("--ics needs --date");
+        }
+        for (person, path) in opts.ics.iter() {
+            let text = fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+            let mut warnings = Vec::new();
+            let busy = read_ics(&text, opts.zone, &opts.tz, until - opts.zone, &mut warnings)
+                .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+            for w in warnings {
+                eprintln!("schedcon: {}: {}", path, w);
+            }
+            for (s, e) in busy {
+                add(person, s + opts.zone, e + opts.zone);
+            }
+        }
     }
 
-    let mut size = 0;
-    for i in 0..(17 - 9) * 60 {
-        if ps[i] > 0 {
-            size = 0;
-        } else {
-            size += 1;
-            if size >= meeting_duration {
-                let i = i + 1 - meeting_duration as usize;
-                let hours = (i / 60) + 9;
-                let minutes = i % 60;
-                println!("{:04}", hours * 100 + minutes);
-                return;
+    let runs = free_runs(&segments, &merge(required));
+    let optional: Vec<Vec<(i64, i64)>> = optional.into_iter().map(|busy| free_runs(&segments, &merge(busy))).collect();
+    let duration = duration as i64;
+    let pieces = feasible_starts(&runs, &optional, duration, opts.quorum);
+    let format = |t: i64| format_time(t, with_date);
+    match opts.mode {
+        Mode::Earliest => match pieces.first() {
+            Some(p) => println!("{}", format(p.from)),
+            None => println!("IMPOSSIBLE"),
+        },
+        Mode::Free => {
+            for &(s, e) in runs.iter() {
+                println!("{} {}", format(s), format(e));
+            }
+        }
+        Mode::Starts => {
+            for p in pieces.iter() {
+                for i in p.from..p.to {
+                    println!("{}", format(i));
+                }
+            }
+        }
+        Mode::Best(k) => {
+            let lunch_off = ((opts.lunch + DAY - hours.start) % DAY) as i64;
+            let lunch = |t: i64| (t - hours.start as i64).div_euclid(day) * day + hours.start as i64 + lunch_off;
+            let best = best_starts(&split_days(&pieces, hours.start as i64), duration, opts.prefer, &lunch, k);
+            if pieces.is_empty() {
+                println!("IMPOSSIBLE");
+            }
+            for i in best {
+                println!("{}", format(i));
+            }
+        }
+        Mode::MaxOptional => {
+            match pieces.iter().rev().max_by_key(|p| p.count) {
+                Some(p) => println!("{} {}", format(p.from), p.count),
+                None => println!("IMPOSSIBLE"),
             }
         }
     }
-    println!("IMPOSSIBLE");
-
 }
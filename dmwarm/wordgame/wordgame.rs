@@ -1,8 +1,90 @@
-use std::io::{self, Read};
-use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::collections::{HashMap, VecDeque, BinaryHeap};
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::File;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use std::process;
+
+trait Words {
+    fn count(&self) -> usize;
+    fn word(&self, idx: usize) -> &str;
+    fn key(&self, idx: usize) -> &str;
+    fn moves(&self) -> Moves;
+    fn neighbours(&self, idx: usize) -> impl Iterator<Item = usize> + '_;
+
+    fn dijkstra(&self, idx1: usize, idx2: usize) -> (Option<Vec<&str>>, usize) {
+        let n = self.count();
+        let mut previous = vec![None; n];
+        let mut expanded = 0;
+        let mut heap = VecDeque::new();
+        heap.push_front(HeapVal {
+            dist: 0,
+            idx: idx1,
+            prev: idx1,
+        });
+        while let Some(item) = heap.pop_back() {
+            if previous[item.idx].is_some() { continue; }
+            previous[item.idx] = Some(item.prev);
+            expanded += 1;
+            if item.idx == idx2 { break; }
+            for neigh in self.neighbours(item.idx) {
+                heap.push_front(HeapVal {
+                    dist: item.dist + 1,
+                    idx: neigh,
+                    prev: item.idx,
+                });
+            }
+        }
+        (self.trace(&previous, idx1, idx2), expanded)
+    }
+
+    // ties on f are broken towards the deeper node, then the lower index
+    fn astar(&self, idx1: usize, idx2: usize) -> (Option<Vec<&str>>, usize) {
+        let n = self.count();
+        let moves = self.moves();
+        let target = letters(self.key(idx2));
+        let mut previous = vec![None; n];
+        let mut expanded = 0;
+        let mut heap = BinaryHeap::new();
+        let h = moves.lower_bound(&letters(self.key(idx1)), &target);
+        heap.push((Reverse(h), 0, Reverse(idx1), idx1));
+        while let Some((_, dist, Reverse(idx), prev)) = heap.pop() {
+            if previous[idx].is_some() { continue; }
+            previous[idx] = Some(prev);
+            expanded += 1;
+            if idx == idx2 { break; }
+            for neigh in self.neighbours(idx) {
+                if previous[neigh].is_some() { continue; }
+                let h = moves.lower_bound(&letters(self.key(neigh)), &target);
+                heap.push((Reverse(dist + 1 + h), dist + 1, Reverse(neigh), idx));
+            }
+        }
+        (self.trace(&previous, idx1, idx2), expanded)
+    }
+
+    fn trace(&self, previous: &[Option<usize>], idx1: usize, idx2: usize) -> Option<Vec<&str>> {
+        let mut order = Vec::new();
+        order.push(self.word(idx2));
+        let mut i = idx2;
+        while i != idx1 {
+            i = match previous[i] {
+                Some(i) => i,
+                None => return None,
+            };
+            order.push(self.word(i));
+        }
+        Some(order)
+    }
+}
 
 struct Graph {
     nodes: Vec<String>,
+    display: Vec<String>,
+    fold: Fold,
+    moves: Moves,
     maybe_neigh: HashMap<String, Vec<usize>>,
     edges: Vec<Vec<usize>>,
     idx1: usize,
@@ -13,71 +95,308 @@ impl Graph {
         self.nodes.push(s.to_string());
     }
     pub fn create_edges(&mut self) {
-        let first = self.nodes[0].clone();
-        let second = self.nodes[1].clone();
-        self.nodes.sort_unstable();
+        let fold = self.fold;
+        let first = fold.apply(&self.nodes[0]);
+        let second = fold.apply(&self.nodes[1]);
+        let mut words: Vec<_> = self.nodes.drain(..).map(|s| (fold.apply(&s), s)).collect();
+        words.sort_unstable();
+        words.dedup_by(|a, b| a.0 == b.0);
+        let (nodes, display) = words.into_iter().unzip();
+        self.nodes = nodes;
+        self.display = display;
         self.idx1 = self.nodes.iter().position(|a| a == &first).unwrap();
         self.idx2 = self.nodes.iter().position(|a| a == &second).unwrap();
-        for (idx, s) in self.nodes.iter().enumerate() {
-            for i in 0..s.len() {
-                let mut buf = String::new();
-                buf.push_str(&s[..i]);
-                buf.push(' ');
-                buf.push_str(&s[i+1..]);
-                self.maybe_neigh.entry(buf).or_insert(Vec::new()).push(idx);
+        let moves = self.moves;
+        let splits: Vec<_> = self.nodes.iter().map(|s| letters(s)).collect();
+        for (idx, letters) in splits.iter().enumerate() {
+            for key in moves.bucket_keys(letters) {
+                self.maybe_neigh.entry(key).or_insert(Vec::new()).push(idx);
             }
         }
-        let mut buf = String::new();
         for (idx1, node) in self.nodes.iter().enumerate() {
-            for i in 0..node.len() {
-                buf.clear();
-                buf.push_str(&node[..i]);
-                buf.push(' ');
-                buf.push_str(&node[i+1..]);
-                for idx2 in self.maybe_neigh[&buf].iter().cloned() {
+            let letters = &splits[idx1];
+            for key in moves.bucket_keys(letters) {
+                for idx2 in self.maybe_neigh[&key].iter().cloned() {
                     if idx1 == idx2 { continue; }
-                    if compare(node, self.nodes[idx2].as_str()) {
+                    if moves.is_move(node, self.nodes[idx2].as_str()) {
                         self.edges[idx1].push(idx2);
                     }
                 }
-                self.edges[idx1].sort_unstable();
             }
+            for word in moves.transpositions(letters) {
+                if let Ok(idx2) = self.nodes.binary_search(&word) {
+                    self.edges[idx1].push(idx2);
+                }
+            }
+            self.edges[idx1].sort_unstable();
+            self.edges[idx1].dedup();
         }
     }
-    pub fn dijkstra(&self) -> Option<Vec<&str>> {
-        let n = self.nodes.len();
-        let mut previous = vec![None; n];
-        let mut heap = VecDeque::new();
-        heap.push_front(HeapVal {
-            dist: 0,
-            idx: self.idx1,
-            prev: self.idx1,
+    pub fn write_index(&self, path: &str) -> io::Result<()> {
+        let offset = |len: usize| u32::try_from(len).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "dictionary too large for an index")
         });
-        while let Some(item) = heap.pop_back() {
-            if previous[item.idx].is_some() { continue; }
-            previous[item.idx] = Some(item.prev);
-            for neigh in self.edges[item.idx].iter().cloned() {
-                heap.push_front(HeapVal {
-                    dist: item.dist + 1,
-                    idx: neigh,
-                    prev: item.idx,
-                });
+        let mut key_off = vec![0u32];
+        let mut keys = Vec::new();
+        let mut disp_off = vec![0u32];
+        let mut disp = Vec::new();
+        let mut adj_off = vec![0u32];
+        let mut adj = Vec::new();
+
+        for ((node, word), edges) in self.nodes.iter().zip(self.display.iter()).zip(self.edges.iter()) {
+            keys.extend_from_slice(node.as_bytes());
+            key_off.push(offset(keys.len())?);
+            disp.extend_from_slice(word.as_bytes());
+            disp_off.push(offset(disp.len())?);
+            adj.extend(edges.iter().map(|&idx| idx as u32));
+            adj_off.push(offset(adj.len())?);
+        }
+
+        let mut payload = Vec::new();
+        for section in [&key_off, &disp_off, &adj_off, &adj].iter() {
+            for v in section.iter() {
+                payload.extend_from_slice(&v.to_le_bytes());
             }
         }
-        let mut order = Vec::new();
-        order.push(self.nodes[self.idx2].as_str());
-        let mut i = self.idx2;
-        while i != self.idx1 {
-            i = match previous[i] {
-                Some(i) => i,
-                None => return None,
-            };
-            order.push(self.nodes[i].as_str());
+        payload.extend_from_slice(&keys);
+        payload.extend_from_slice(&disp);
+
+        let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+        out.extend_from_slice(INDEX_MAGIC);
+        for v in [
+            INDEX_VERSION,
+            self.fold.flags() | self.moves.flags() << 2,
+            self.nodes.len() as u32,
+            adj.len() as u32,
+            keys.len() as u32,
+            disp.len() as u32,
+        ].iter() {
+            out.extend_from_slice(&v.to_le_bytes());
         }
-        Some(order)
+        out.extend_from_slice(&checksum(&payload).to_le_bytes());
+        out.extend_from_slice(&payload);
+        File::create(path)?.write_all(&out)
+    }
+}
+impl Words for Graph {
+    fn count(&self) -> usize {
+        self.nodes.len()
+    }
+    fn word(&self, idx: usize) -> &str {
+        self.display[idx].as_str()
+    }
+    fn key(&self, idx: usize) -> &str {
+        self.nodes[idx].as_str()
+    }
+    fn moves(&self) -> Moves {
+        self.moves
+    }
+    fn neighbours(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges[idx].iter().cloned()
+    }
+}
+
+// magic, version, fold and move flags, nodes, neighbour links, key and
+// display blob lengths, checksum
+const INDEX_MAGIC: &[u8; 4] = b"WGIX";
+const INDEX_VERSION: u32 = 4;
+const HEADER_LEN: usize = 4 + 6 * 4 + 8;
+
+extern "C" {
+    fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut u8;
+    fn munmap(addr: *mut u8, len: usize) -> i32;
+}
+const PROT_READ: i32 = 1;
+const MAP_PRIVATE: i32 = 2;
+
+struct Mmap {
+    ptr: *mut u8,
+    len: usize,
+}
+impl Mmap {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty index"));
+        }
+        let ptr = unsafe {
+            mmap(std::ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr as usize == usize::MAX {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+}
+impl Deref for Mmap {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
+
+struct Index {
+    map: Mmap,
+    fold: Fold,
+    moves: Moves,
+    nodes: usize,
+    key_off: usize,
+    disp_off: usize,
+    adj_off: usize,
+    adj: usize,
+    keys: usize,
+    disp: usize,
+}
+impl Index {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let map = Mmap::open(path)?;
+        if map.len() < HEADER_LEN || &map[..4] != INDEX_MAGIC {
+            return Err(invalid("not a wordgame index"));
+        }
+        let header = |i: usize| read_u32(&map, 4 + 4 * i) as usize;
+        if header(0) != INDEX_VERSION as usize {
+            return Err(invalid("unsupported index version"));
+        }
+        let fold = Fold::from_flags(header(1) as u32);
+        let moves = Moves::from_flags(header(1) as u32 >> 2);
+        let (nodes, links, keys_len, disp_len) = (header(2), header(3), header(4), header(5));
+
+        let section = |start: usize, len: usize, width: usize| {
+            len.checked_mul(width).and_then(|bytes| start.checked_add(bytes)).ok_or_else(|| invalid("truncated index"))
+        };
+        let key_off = HEADER_LEN;
+        let disp_off = section(key_off, nodes + 1, 4)?;
+        let adj_off = section(disp_off, nodes + 1, 4)?;
+        let adj = section(adj_off, nodes + 1, 4)?;
+        let keys = section(adj, links, 4)?;
+        let disp = section(keys, keys_len, 1)?;
+        if Some(map.len()) != disp.checked_add(disp_len) {
+            return Err(invalid("truncated index"));
+        }
+        let mut sum = [0; 8];
+        sum.copy_from_slice(&map[HEADER_LEN - 8..HEADER_LEN]);
+        if u64::from_le_bytes(sum) != checksum(&map[HEADER_LEN..]) {
+            return Err(invalid("index checksum mismatch"));
+        }
+
+        let index = Self {
+            map,
+            fold,
+            moves,
+            nodes,
+            key_off,
+            disp_off,
+            adj_off,
+            adj,
+            keys,
+            disp,
+        };
+        if !index.valid_offsets(key_off, keys_len)
+            || !index.valid_offsets(disp_off, disp_len)
+            || !index.valid_offsets(adj_off, links)
+            || (0..links).any(|i| index.get(adj, i) >= nodes)
+        {
+            return Err(invalid("index offsets out of range"));
+        }
+        let utf8 = |offsets, blob| (0..nodes).all(|i| {
+            std::str::from_utf8(&index.map[blob + index.get(offsets, i)..blob + index.get(offsets, i + 1)]).is_ok()
+        });
+        if !utf8(key_off, keys) || !utf8(disp_off, disp) {
+            return Err(invalid("index words are not utf-8"));
+        }
+        Ok(index)
+    }
+    // offset tables start at zero, never decrease and end at the length of
+    // the section they point into
+    fn valid_offsets(&self, offsets: usize, len: usize) -> bool {
+        self.get(offsets, 0) == 0
+            && self.get(offsets, self.nodes) == len
+            && (0..self.nodes).all(|i| self.get(offsets, i) <= self.get(offsets, i + 1))
+    }
+    fn get(&self, section: usize, i: usize) -> usize {
+        read_u32(&self.map, section + 4 * i) as usize
+    }
+    // open has checked that every word is in its blob and utf-8
+    fn string(&self, offsets: usize, blob: usize, idx: usize) -> &str {
+        let start = blob + self.get(offsets, idx);
+        let end = blob + self.get(offsets, idx + 1);
+        unsafe { std::str::from_utf8_unchecked(&self.map[start..end]) }
+    }
+    // the command line options the index was built with
+    pub fn options(&self) -> String {
+        let mut res = String::new();
+        if self.fold.case { res.push_str(" --fold-case"); }
+        if self.fold.diacritics { res.push_str(" --fold-diacritics"); }
+        let moves = [(self.moves.substitute, "sub"), (self.moves.transpose, "swap"), (self.moves.anagram, "anagram")];
+        let moves: Vec<_> = moves.iter().filter(|m| m.0).map(|m| m.1).collect();
+        res.push_str(" --moves ");
+        res.push_str(&moves.join(","));
+        res
+    }
+    pub fn find(&self, word: &str) -> Option<usize> {
+        self.find_key(&self.fold.apply(word))
+    }
+    fn find_key(&self, word: &str) -> Option<usize> {
+        let (mut lo, mut hi) = (0, self.nodes);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.key(mid) < word {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo < self.nodes && self.key(lo) == word {
+            Some(lo)
+        } else {
+            None
+        }
+    }
+}
+impl Words for Index {
+    fn count(&self) -> usize {
+        self.nodes
+    }
+    fn word(&self, idx: usize) -> &str {
+        self.string(self.disp_off, self.disp, idx)
+    }
+    fn key(&self, idx: usize) -> &str {
+        self.string(self.key_off, self.keys, idx)
+    }
+    fn moves(&self) -> Moves {
+        self.moves
+    }
+    fn neighbours(&self, idx: usize) -> impl Iterator<Item = usize> + '_ {
+        let links = self.get(self.adj_off, idx)..self.get(self.adj_off, idx + 1);
+        links.map(move |i| self.get(self.adj, i))
     }
 }
 
+fn read_u32(buf: &[u8], at: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[at..at + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+// FNV-1a
+fn checksum(buf: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for b in buf {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 #[derive(Eq,PartialEq,Ord,PartialOrd)]
 struct HeapVal {
     dist: u32,
@@ -85,10 +404,112 @@ struct HeapVal {
     prev: usize,
 }
 
+#[derive(Copy,Clone,Default)]
+struct Fold {
+    case: bool,
+    diacritics: bool,
+}
+impl Fold {
+    pub fn apply(&self, s: &str) -> String {
+        let mut res = String::with_capacity(s.len());
+        for c in s.chars() {
+            if self.diacritics && is_combining(c) { continue; }
+            let c = if self.diacritics { strip_diacritic(c) } else { c };
+            if self.case {
+                res.extend(c.to_lowercase());
+            } else {
+                res.push(c);
+            }
+        }
+        res
+    }
+    pub fn flags(&self) -> u32 {
+        self.case as u32 | (self.diacritics as u32) << 1
+    }
+    pub fn from_flags(flags: u32) -> Self {
+        Self {
+            case: flags & 1 != 0,
+            diacritics: flags & 2 != 0,
+        }
+    }
+}
+
+fn is_combining(c: char) -> bool {
+    matches!(c as u32, 0x0300..=0x036f | 0x1ab0..=0x1aff | 0x1dc0..=0x1dff | 0x20d0..=0x20ff | 0xfe20..=0xfe2f)
+}
+
+fn strip_diacritic(c: char) -> char {
+    if c.is_ascii() { return c; }
+    if c.is_uppercase() {
+        let lower = c.to_lowercase().next().unwrap();
+        let base = strip_diacritic(lower);
+        if base == lower { return c; }
+        return base.to_uppercase().next().unwrap();
+    }
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+        'ď' | 'đ' => 'd',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+        'ĥ' | 'ħ' => 'h',
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => 'i',
+        'ĵ' => 'j',
+        'ķ' => 'k',
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => 'l',
+        'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => 'o',
+        'ŕ' | 'ŗ' | 'ř' => 'r',
+        'ś' | 'ŝ' | 'ş' | 'š' => 's',
+        'ţ' | 'ť' | 'ŧ' => 't',
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+        'ŵ' => 'w',
+        'ý' | 'ÿ' | 'ŷ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        c => c,
+    }
+}
+
+// a letter is a char together with any combining marks following it
+fn letters(s: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if i > start && !is_combining(c) {
+            res.push(&s[start..i]);
+            start = i;
+        }
+    }
+    if start < s.len() {
+        res.push(&s[start..]);
+    }
+    res
+}
+
+fn wildcard(letters: &[&str], i: usize) -> String {
+    let mut buf = String::new();
+    for (j, l) in letters.iter().enumerate() {
+        buf.push_str(if i == j { " " } else { l });
+    }
+    buf
+}
+
+fn sorted_letters(letters: &[&str]) -> String {
+    let mut sorted = letters.to_vec();
+    sorted.sort_unstable();
+    sorted.concat()
+}
+
+// ascii words have one letter per byte, so they can skip the split
 fn compare(a: &str, b: &str) -> bool {
+    if a.is_ascii() && b.is_ascii() {
+        return a.len() == b.len() && a.bytes().zip(b.bytes()).filter(|(a, b)| a != b).count() == 1;
+    }
+    let a = letters(a);
+    let b = letters(b);
+    if a.len() != b.len() { return false; }
     let mut diffs = 0;
-    assert!(a.len() == b.len());
-    for (a,b) in a.as_bytes().iter().zip(b.as_bytes()) {
+    for (a,b) in a.iter().zip(b.iter()) {
         if a != b {
             diffs += 1;
         }
@@ -96,16 +517,374 @@ fn compare(a: &str, b: &str) -> bool {
     diffs == 1
 }
 
+fn is_transposition(a: &str, b: &str) -> bool {
+    if a.is_ascii() && b.is_ascii() {
+        return is_swap(a.as_bytes(), b.as_bytes());
+    }
+    is_swap(&letters(a), &letters(b))
+}
+
+fn is_swap<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    if a.len() != b.len() { return false; }
+    match (0..a.len()).find(|&i| a[i] != b[i]) {
+        Some(i) => i + 1 < a.len() && a[i] == b[i+1] && a[i+1] == b[i] && a[i+2..] == b[i+2..],
+        None => false,
+    }
+}
+
+fn is_anagram(a: &str, b: &str) -> bool {
+    if a == b || a.len() != b.len() { return false; }
+    if a.is_ascii() && b.is_ascii() {
+        let mut counts = [0i32; 128];
+        for (x, y) in a.bytes().zip(b.bytes()) {
+            counts[x as usize] += 1;
+            counts[y as usize] -= 1;
+        }
+        return counts.iter().all(|&c| c == 0);
+    }
+    sorted_letters(&letters(a)) == sorted_letters(&letters(b))
+}
+
+#[derive(Copy,Clone)]
+struct Moves {
+    substitute: bool,
+    transpose: bool,
+    anagram: bool,
+}
+impl Default for Moves {
+    fn default() -> Self {
+        Self {
+            substitute: true,
+            transpose: false,
+            anagram: false,
+        }
+    }
+}
+impl Moves {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut moves = Self {
+            substitute: false,
+            transpose: false,
+            anagram: false,
+        };
+        for m in s.split(',') {
+            match m {
+                "sub" => moves.substitute = true,
+                "swap" => moves.transpose = true,
+                "anagram" => moves.anagram = true,
+                _ => return None,
+            }
+        }
+        Some(moves)
+    }
+    // wildcard keys always contain a space and anagram keys never do, so
+    // both kinds of bucket can share one map
+    pub fn bucket_keys(&self, letters: &[&str]) -> Vec<String> {
+        let mut keys = Vec::new();
+        if self.substitute {
+            keys.extend((0..letters.len()).map(|i| wildcard(letters, i)));
+        }
+        if self.anagram {
+            keys.push(sorted_letters(letters));
+        }
+        keys
+    }
+    pub fn transpositions(&self, letters: &[&str]) -> Vec<String> {
+        let mut res = Vec::new();
+        if !self.transpose { return res; }
+        for i in 1..letters.len() {
+            if letters[i-1] == letters[i] { continue; }
+            let mut swapped = letters.to_vec();
+            swapped.swap(i-1, i);
+            res.push(swapped.concat());
+        }
+        res
+    }
+    // admissible and consistent: a substitution fixes at most one position,
+    // a transposition at most two, and an anagram move anything
+    pub fn lower_bound(&self, a: &[&str], b: &[&str]) -> u32 {
+        if a.len() != b.len() { return 0; }
+        let diffs = a.iter().zip(b.iter()).filter(|(a, b)| a != b).count() as u32;
+        if self.anagram {
+            diffs.min(1)
+        } else if self.transpose {
+            diffs.div_ceil(2)
+        } else {
+            diffs
+        }
+    }
+    pub fn is_move(&self, a: &str, b: &str) -> bool {
+        (self.substitute && compare(a, b))
+            || (self.transpose && is_transposition(a, b))
+            || (self.anagram && is_anagram(a, b))
+    }
+    pub fn flags(&self) -> u32 {
+        self.substitute as u32 | (self.transpose as u32) << 1 | (self.anagram as u32) << 2
+    }
+    pub fn from_flags(flags: u32) -> Self {
+        Self {
+            substitute: flags & 1 != 0,
+            transpose: flags & 2 != 0,
+            anagram: flags & 4 != 0,
+        }
+    }
+}
+
+fn solve<W: Words>(g: &W, idx1: usize, idx2: usize, opts: &Options) {
+    let (path, expanded) = if opts.astar {
+        g.astar(idx1, idx2)
+    } else {
+        g.dijkstra(idx1, idx2)
+    };
+    if opts.expanded {
+        eprintln!("expanded {}", expanded);
+    }
+    print_path(path);
+}
+
+fn print_path(path: Option<Vec<&str>>) {
+    match path {
+        Some(path) => for node in path.into_iter().rev() {
+            println!("{}", node);
+        },
+        None => println!("IMPOSSIBLE"),
+    }
+}
+
+fn distances<W: Words>(g: &W, from: usize) -> Vec<Option<u32>> {
+    let mut dist = vec![None; g.count()];
+    let mut queue = VecDeque::new();
+    dist[from] = Some(0);
+    queue.push_front(from);
+    while let Some(idx) = queue.pop_back() {
+        let d = dist[idx].unwrap();
+        for neigh in g.neighbours(idx) {
+            if dist[neigh].is_none() {
+                dist[neigh] = Some(d + 1);
+                queue.push_front(neigh);
+            }
+        }
+    }
+    dist
+}
+
+fn eccentricity(dist: &[Option<u32>]) -> (u32, usize) {
+    let mut best = (0, 0);
+    for (idx, d) in dist.iter().enumerate() {
+        if let Some(d) = *d {
+            if d > best.0 {
+                best = (d, idx);
+            }
+        }
+    }
+    best
+}
+
+// nodes reachable from `from` in breadth-first order, so the last one is
+// the farthest; fills in `dist` for them and leaves resetting it to the caller
+fn sweep<W: Words>(g: &W, from: usize, dist: &mut [Option<u32>]) -> Vec<usize> {
+    let mut order = vec![from];
+    dist[from] = Some(0);
+    let mut i = 0;
+    while i < order.len() {
+        let idx = order[i];
+        let d = dist[idx].unwrap();
+        for neigh in g.neighbours(idx) {
+            if dist[neigh].is_none() {
+                dist[neigh] = Some(d + 1);
+                order.push(neigh);
+            }
+        }
+        i += 1;
+    }
+    order
+}
+
+fn farthest<W: Words>(g: &W, from: usize, dist: &mut [Option<u32>]) -> (u32, usize) {
+    let order = sweep(g, from, dist);
+    let to = *order.last().unwrap();
+    let d = dist[to].unwrap();
+    for idx in order {
+        dist[idx] = None;
+    }
+    (d, to)
+}
+
+// exact diameter of the component holding `start` (iFUB): two sweeps find a
+// long path whose middle node roots a search tree, and once the nodes below
+// level i are all that is left none of them can beat 2(i-1)
+fn component_diameter<W: Words>(g: &W, start: usize, dist: &mut [Option<u32>]) -> (u32, usize, usize) {
+    let (_, a) = farthest(g, start, dist);
+    let (d, b) = farthest(g, a, dist);
+    let order = sweep(g, b, dist);
+    let mut mid = a;
+    for _ in 0..d / 2 {
+        let level = dist[mid].unwrap() - 1;
+        mid = g.neighbours(mid).find(|&n| dist[n] == Some(level)).unwrap();
+    }
+    for idx in order {
+        dist[idx] = None;
+    }
+
+    let order = sweep(g, mid, dist);
+    let levels: Vec<u32> = order.iter().map(|&idx| dist[idx].unwrap()).collect();
+    for &idx in &order {
+        dist[idx] = None;
+    }
+    let mut best = (d, a, b);
+    let mut end = order.len();
+    let mut i = levels[end - 1];
+    while best.0 < 2 * i {
+        let begin = levels.partition_point(|&l| l < i);
+        for &from in &order[begin..end] {
+            let (d, to) = farthest(g, from, dist);
+            if d > best.0 {
+                best = (d, from, to);
+            }
+        }
+        end = begin;
+        i -= 1;
+    }
+    best
+}
+
+fn print_stats<W: Words>(g: &W, word: usize, opts: &Options) {
+    let n = g.count();
+    let edges: usize = (0..n).map(|i| g.neighbours(i).count()).sum();
+    println!("words {}", n);
+    println!("edges {}", edges / 2);
+
+    let mut dist = vec![None; n];
+    let mut seen = vec![false; n];
+    let mut sizes = Vec::new();
+    let mut diameter = (0, 0, 0);
+    for start in 0..n {
+        if seen[start] { continue; }
+        let order = sweep(g, start, &mut dist);
+        for &idx in &order {
+            seen[idx] = true;
+            dist[idx] = None;
+        }
+        sizes.push(order.len());
+        if opts.diameter && order.len() > 1 {
+            let d = component_diameter(g, start, &mut dist);
+            if d.0 > diameter.0 {
+                diameter = d;
+            }
+        }
+    }
+    println!("components {}", sizes.len());
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    let mut i = 0;
+    while i < sizes.len() {
+        let same = sizes[i..].iter().take_while(|&&s| s == sizes[i]).count();
+        println!("component {} x{}", sizes[i], same);
+        i += same;
+    }
+
+    let isolated: Vec<_> = (0..n).filter(|&i| g.neighbours(i).next().is_none()).collect();
+    println!("isolated {}", isolated.len());
+    for idx in isolated {
+        println!("{}", g.word(idx));
+    }
+
+    let (ecc, far) = eccentricity(&distances(g, word));
+    println!("eccentricity {} {} {}", g.word(word), ecc, g.word(far));
+    if !opts.diameter { return; }
+    println!("diameter {} {} {}", diameter.0, g.word(diameter.1), g.word(diameter.2));
+}
+
+#[derive(Default)]
+struct Options {
+    build_index: Option<String>,
+    index: Option<String>,
+    stats: bool,
+    diameter: bool,
+    fold: Fold,
+    moves: Option<Moves>,
+    astar: bool,
+    expanded: bool,
+}
+
+fn usage() -> ! {
+    eprintln!("usage: wordgame [--stats [--diameter]] [--fold-case] [--fold-diacritics] [--moves sub,swap,anagram] [--astar] [--expanded] [--build-index FILE | --index FILE] < input");
+    eprintln!("--diameter is exact and usually takes a few searches per component, but up to one per word on very regular dictionaries");
+    process::exit(2);
+}
+
+fn parse_args() -> Options {
+    let mut opts = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--build-index" => opts.build_index = Some(args.next().unwrap_or_else(|| usage())),
+            "--index" => opts.index = Some(args.next().unwrap_or_else(|| usage())),
+            "--stats" => opts.stats = true,
+            "--diameter" => opts.diameter = true,
+            "--astar" => opts.astar = true,
+            "--expanded" => opts.expanded = true,
+            "--fold-case" => opts.fold.case = true,
+            "--fold-diacritics" => opts.fold.diacritics = true,
+            "--moves" => opts.moves = Some(args.next().and_then(|m| Moves::parse(&m)).unwrap_or_else(|| usage())),
+            _ => usage(),
+        }
+    }
+    if (opts.diameter && !opts.stats) || (opts.index.is_some() && opts.build_index.is_some()) {
+        usage();
+    }
+    opts
+}
+
 fn main() {
+    let opts = parse_args();
+
     let mut buf = String::new();
     io::stdin().read_to_string(&mut buf).unwrap();
 
+    // with a prebuilt index the input is just pairs of words to connect, or
+    // for --stats the word to measure eccentricity from
+    if let Some(path) = &opts.index {
+        let index = Index::open(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        // folding and moves are fixed when the index is built
+        let fold = (opts.fold.case && !index.fold.case) || (opts.fold.diacritics && !index.fold.diacritics);
+        if fold || opts.moves.is_some_and(|m| m.flags() != index.moves.flags()) {
+            eprintln!("{}: index was built with{}", path, index.options());
+            process::exit(1);
+        }
+        let mut split = buf.split_whitespace();
+        if opts.stats {
+            let word = split.next().unwrap_or_else(|| usage());
+            let idx = index.find(word).unwrap_or_else(|| {
+                eprintln!("{}: not in {}", word, path);
+                process::exit(1);
+            });
+            print_stats(&index, idx, &opts);
+            return;
+        }
+        let mut first = true;
+        while let (Some(a), Some(b)) = (split.next(), split.next()) {
+            if !first { println!(); }
+            first = false;
+            match (index.find(a), index.find(b)) {
+                (Some(idx1), Some(idx2)) => solve(&index, idx1, idx2, &opts),
+                _ => print_path(None),
+            }
+        }
+        return;
+    }
+
     let mut split = buf.split_whitespace();
     let _ = split.next().unwrap();
     let n = split.next().unwrap().parse::<usize>().unwrap() + 2;
 
     let mut g = Graph {
         nodes: Vec::with_capacity(n),
+        display: Vec::new(),
+        fold: opts.fold,
+        moves: opts.moves.unwrap_or_default(),
         maybe_neigh: HashMap::with_capacity(6*n),
         edges: vec![Vec::new(); n],
         idx1: 0,
@@ -116,11 +895,15 @@ fn main() {
         g.add_neighs(split.next().unwrap().to_string());
     }
     g.create_edges();
-    match g.dijkstra() {
-        Some(path) => for node in path.into_iter().rev() {
-            println!("{}", node);
-        },
-        None => println!("IMPOSSIBLE"),
+    if let Some(path) = &opts.build_index {
+        if let Err(e) = g.write_index(path) {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    if opts.stats {
+        print_stats(&g, g.idx1, &opts);
+    } else {
+        solve(&g, g.idx1, g.idx2, &opts);
     }
 }
-
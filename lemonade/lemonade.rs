@@ -1,63 +1,841 @@
 use std::io::{self, BufRead, BufReader};
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::cmp::Reverse;
+use std::cmp::Ordering;
+use std::ops::{Index, IndexMut};
+use std::time::{Duration, Instant};
+use std::env;
+use std::process;
 
-#[derive(Copy,Clone)]
+// Only constructed by Kinds, so a Kind always names an interned kind.
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
 struct Kind(usize);
 
-struct Graph {
-    kinds: HashMap<String, Kind>,
-    exchange: Vec<f64>,
+// Interns kind names into dense indices, the start kind being Kind(0).
+struct Kinds {
+    names: Vec<String>,
+    ids: HashMap<String, Kind>,
 }
 
-impl Graph {
-    pub fn new(size: usize) -> Self {
-        let mut kinds = HashMap::with_capacity(size);
-        kinds.insert(String::from("pink"), Kind(0));
+impl Kinds {
+    fn new(start: &str) -> Self {
+        let mut kinds = Kinds { names: Vec::new(), ids: HashMap::new() };
+        kinds.intern(start);
+        kinds
+    }
+    fn get(&self, name: &str) -> Option<Kind> {
+        self.ids.get(name).copied()
+    }
+    fn intern(&mut self, name: &str) -> Kind {
+        if let Some(kind) = self.get(name) {
+            return kind;
+        }
+        let kind = Kind(self.names.len());
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), kind);
+        kind
+    }
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+    fn start(&self) -> Kind {
+        Kind(0)
+    }
+    fn iter(&self) -> impl Iterator<Item = Kind> {
+        (0..self.names.len()).map(Kind)
+    }
+}
 
-        let mut exchange = Vec::with_capacity(size);
-        exchange.push(0.0);
+// Per kind values, grown alongside Kinds. Indexing is bounds checked.
+struct PerKind<T>(Vec<T>);
 
-        Self {
-            kinds,
-            exchange,
+impl<T> Index<Kind> for PerKind<T> {
+    type Output = T;
+    fn index(&self, kind: Kind) -> &T {
+        &self.0[kind.0]
+    }
+}
+impl<T> IndexMut<Kind> for PerKind<T> {
+    fn index_mut(&mut self, kind: Kind) -> &mut T {
+        &mut self.0[kind.0]
+    }
+}
+
+// volume is the most of the offered kind the counterparty will hand over
+struct Trade {
+    offered: String,
+    wanted: String,
+    rate: String,
+    volume: Option<f64>,
+}
+
+// The best exchange of a kind may improve after it has been traded away, so
+// every improvement gets its own step pointing at the step it traded from.
+struct Step {
+    trade: usize,
+    prev: Option<usize>,
+}
+
+// How much of a kind one unit of the start kind can become. Combining a
+// trade into an amount must be monotone so the best amounts can be kept.
+trait Amount: Clone + PartialOrd {
+    fn one() -> Self;
+    fn parse_rate(rate: &str) -> Self;
+    fn times(&self, rate: &Self) -> Self;
+    fn exceeds(&self, cap: f64) -> bool;
+    fn to_f64(&self) -> f64;
+    fn describe(&self) -> String;
+}
+
+// floating point amounts are kept as base two logarithms
+impl Amount for f64 {
+    fn one() -> Self {
+        0.0
+    }
+    fn parse_rate(rate: &str) -> Self {
+        rate.parse::<f64>().unwrap().log2()
+    }
+    fn times(&self, rate: &Self) -> Self {
+        self + rate
+    }
+    fn exceeds(&self, cap: f64) -> bool {
+        self.exp2() > cap
+    }
+    fn to_f64(&self) -> f64 {
+        self.exp2()
+    }
+    fn describe(&self) -> String {
+        format!("{:.10}", self.exp2())
+    }
+}
+
+// little endian limbs in base 10^9, without leading zero limbs
+#[derive(Clone,PartialEq,Eq)]
+struct BigUint(Vec<u32>);
+
+const LIMB: u64 = 1_000_000_000;
+
+impl BigUint {
+    fn parse(digits: &str) -> Self {
+        let digits = digits.as_bytes();
+        let mut limbs = Vec::with_capacity(digits.len() / 9 + 1);
+        let mut end = digits.len();
+        while end > 0 {
+            let start = end.saturating_sub(9);
+            let chunk = std::str::from_utf8(&digits[start..end]).unwrap();
+            limbs.push(chunk.parse().unwrap());
+            end = start;
+        }
+        let mut res = BigUint(limbs);
+        res.trim();
+        res
+    }
+    fn trim(&mut self) {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+    }
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+    fn pow10(k: u32) -> Self {
+        let mut limbs = vec![0; (k / 9) as usize];
+        limbs.push(10u32.pow(k % 9));
+        BigUint(limbs)
+    }
+    fn mul(&self, other: &Self) -> Self {
+        if self.is_zero() || other.is_zero() {
+            return BigUint(Vec::new());
+        }
+        let mut res = vec![0u64; self.0.len() + other.0.len()];
+        for (i, &a) in self.0.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in other.0.iter().enumerate() {
+                let cur = res[i + j] + a as u64 * b as u64 + carry;
+                res[i + j] = cur % LIMB;
+                carry = cur / LIMB;
+            }
+            res[i + other.0.len()] += carry;
+        }
+        let mut res = BigUint(res.into_iter().map(|l| l as u32).collect());
+        res.trim();
+        res
+    }
+    fn div_rem_small(&self, d: u32) -> (Self, u32) {
+        let mut res = vec![0; self.0.len()];
+        let mut rem = 0u64;
+        for i in (0..self.0.len()).rev() {
+            let cur = rem * LIMB + self.0[i] as u64;
+            res[i] = (cur / d as u64) as u32;
+            rem = cur % d as u64;
+        }
+        let mut res = BigUint(res);
+        res.trim();
+        (res, rem as u32)
+    }
+    fn to_digits(&self) -> String {
+        let mut res = match self.0.last() {
+            Some(top) => top.to_string(),
+            None => return String::from("0"),
+        };
+        for limb in self.0.iter().rev().skip(1) {
+            res.push_str(&format!("{:09}", limb));
+        }
+        res
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.len().cmp(&other.0.len())
+            .then_with(|| self.0.iter().rev().cmp(other.0.iter().rev()))
+    }
+}
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Parsed decimals are at most 10^MAX_SCALE away from their digits, well
+// past anything an f64 rate or cap can hold.
+const MAX_SCALE: i64 = 1000;
+
+// An exact rational mant / 10^scale. Decimal rates only ever multiply into
+// rationals of this form, so no general denominators are needed.
+#[derive(Clone)]
+struct Decimal {
+    mant: BigUint,
+    scale: u32,
+}
+
+impl Decimal {
+    fn parse(s: &str) -> Option<Self> {
+        let (num, exp) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], s[i+1..].parse::<i64>().ok()?),
+            None => (s, 0),
+        };
+        let (int, frac) = match num.find('.') {
+            Some(i) => (&num[..i], &num[i+1..]),
+            None => (num, ""),
+        };
+        if int.is_empty() && frac.is_empty() { return None; }
+        if !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) { return None; }
+        let mut mant = BigUint::parse(&format!("{}{}", int, frac));
+        let scale = (frac.len() as i64).checked_sub(exp).filter(|s| s.abs() <= MAX_SCALE)?;
+        if scale < 0 {
+            mant = mant.mul(&BigUint::pow10(-scale as u32));
         }
+        let mut res = Decimal { mant, scale: scale.max(0) as u32 };
+        res.normalize();
+        Some(res)
+    }
+    fn normalize(&mut self) {
+        while self.scale > 0 {
+            let (q, r) = self.mant.div_rem_small(10);
+            if r != 0 { break; }
+            self.mant = q;
+            self.scale -= 1;
+        }
+        if self.mant.is_zero() {
+            self.scale = 0;
+        }
+    }
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        let a = self.mant.mul(&BigUint::pow10(scale - self.scale));
+        let b = other.mant.mul(&BigUint::pow10(scale - other.scale));
+        a.cmp(&b)
     }
+}
 
-    pub fn get_exch(&self, kind: Kind) -> f64 {
-        unsafe {
-            *self.exchange.get_unchecked(kind.0)
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        Decimal::cmp(self, other) == Ordering::Equal
+    }
+}
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(Decimal::cmp(self, other))
+    }
+}
+
+impl Amount for Decimal {
+    fn one() -> Self {
+        Decimal { mant: BigUint::parse("1"), scale: 0 }
+    }
+    fn parse_rate(rate: &str) -> Self {
+        Decimal::parse(rate).unwrap()
+    }
+    fn times(&self, rate: &Self) -> Self {
+        let mut res = Decimal {
+            mant: self.mant.mul(&rate.mant),
+            scale: self.scale + rate.scale,
+        };
+        res.normalize();
+        res
+    }
+    fn exceeds(&self, cap: f64) -> bool {
+        *self > Decimal::parse(&format!("{:?}", cap)).unwrap()
+    }
+    fn to_f64(&self) -> f64 {
+        self.describe().parse().unwrap()
+    }
+    fn describe(&self) -> String {
+        let digits = self.mant.to_digits();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return digits;
         }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        format!("{}.{}", int, frac)
     }
-    pub fn set_exch(&mut self, kind: Kind, val: f64) {
-        unsafe {
-            *self.exchange.get_unchecked_mut(kind.0) = val;
+}
+
+struct Graph<T> {
+    kinds: Kinds,
+    exchange: PerKind<T>,
+    best: PerKind<Option<usize>>,
+    steps: Vec<Step>,
+}
+
+impl<T: Amount> Graph<T> {
+    pub fn new(start: &str) -> Self {
+        Self {
+            kinds: Kinds::new(start),
+            exchange: PerKind(vec![T::one()]),
+            best: PerKind(vec![None]),
+            steps: Vec::new(),
         }
     }
 
-    pub fn add_edge(&mut self, from: &str, to: &str, exch: f64) {
+    pub fn get_exch(&self, kind: Kind) -> &T {
+        &self.exchange[kind]
+    }
+    pub fn set_exch(&mut self, kind: Kind, val: T) {
+        self.exchange[kind] = val;
+    }
+
+    fn add_step(&mut self, from: Kind, trade: usize) -> Option<usize> {
+        self.steps.push(Step {
+            trade,
+            prev: self.best[from],
+        });
+        Some(self.steps.len() - 1)
+    }
+
+    pub fn add_edge(&mut self, from: &str, to: &str, exch: &T, trade: usize) {
         let from_idx = match self.kinds.get(from) {
-            Some(from_idx) => *from_idx,
+            Some(from_idx) => from_idx,
             None => return, // We don't have any of this kind, so this is not usable
         };
 
-        let past_exch = self.get_exch(from_idx);
-        let exchange = exch + past_exch;
+        let exchange = self.get_exch(from_idx).times(exch);
 
-        match self.kinds.get(to).map(|to_idx| *to_idx) {
+        match self.kinds.get(to) {
             Some(to_idx) => {
-                let prev_exchange = self.get_exch(to_idx);
-                if exchange > prev_exchange {
+                if exchange > *self.get_exch(to_idx) {
                     self.set_exch(to_idx, exchange);
+                    self.best[to_idx] = self.add_step(from_idx, trade);
                 }
             }
             None => {
-                let kind = Kind(self.exchange.len());
+                self.kinds.intern(to);
+                self.exchange.0.push(exchange);
+                let step = self.add_step(from_idx, trade);
+                self.best.0.push(step);
+            }
+        }
+    }
+
+    // Every interned kind has exactly one exchange and best step, the name
+    // lookup agrees with the arena, and each best chain leads back to the
+    // start kind through steps that were recorded earlier. The start kind
+    // only has a best step when a cycle improved on the unit it began with.
+    fn check(&self) {
+        let n = self.kinds.len();
+        assert_eq!(self.kinds.ids.len(), n);
+        assert_eq!(self.exchange.0.len(), n);
+        assert_eq!(self.best.0.len(), n);
+        for (kind, name) in self.kinds.iter().zip(self.kinds.names.iter()) {
+            assert_eq!(self.kinds.get(name), Some(kind));
+        }
+        let start = self.kinds.start();
+        assert!(*self.get_exch(start) >= T::one());
+        for kind in self.kinds.iter() {
+            let mut step = self.best[kind];
+            assert!(kind == start || step.is_some());
+            while let Some(s) = step {
+                let prev = self.steps[s].prev;
+                assert!(prev.is_none_or(|p| p < s));
+                step = prev;
+            }
+        }
+    }
+
+    // the trades leading to the best exchange of a kind, in the order they
+    // are made
+    pub fn chain(&self, kind: Kind) -> Vec<usize> {
+        let mut chain = Vec::new();
+        let mut step = self.best[kind];
+        while let Some(s) = step {
+            chain.push(self.steps[s].trade);
+            step = self.steps[s].prev;
+        }
+        chain.reverse();
+        chain
+    }
+}
+
+// Treats the trades as an unordered market that can be repeated at will and
+// looks for a cycle whose rates multiply to more than one with Bellman-Ford
+// over -log2(rate). Returns the trades of the cycle in the order to make them.
+fn find_arbitrage(trades: &[Trade]) -> Option<Vec<usize>> {
+    let mut kinds: HashMap<&str, usize> = HashMap::new();
+    let mut edges = Vec::with_capacity(trades.len());
+    for (i, trade) in trades.iter().enumerate() {
+        let rate: f64 = trade.rate.parse().unwrap();
+        if rate <= 0.0 { continue; }
+        let next = kinds.len();
+        let from = *kinds.entry(trade.wanted.as_str()).or_insert(next);
+        let next = kinds.len();
+        let to = *kinds.entry(trade.offered.as_str()).or_insert(next);
+        edges.push((from, to, -rate.log2(), i));
+    }
+
+    // every kind starts at distance zero, as if from a virtual source
+    let n = kinds.len();
+    let mut dist = vec![0.0; n];
+    let mut pred: Vec<Option<usize>> = vec![None; n];
+    let mut last = None;
+    for _ in 0..n {
+        last = None;
+        for (e, &(from, to, w, _)) in edges.iter().enumerate() {
+            if dist[from] + w < dist[to] - 1e-12 {
+                dist[to] = dist[from] + w;
+                pred[to] = Some(e);
+                last = Some(to);
+            }
+        }
+        last?;
+    }
+
+    // still relaxing after n rounds, so walking back n steps lands on a cycle
+    let mut kind = last?;
+    for _ in 0..n {
+        kind = edges[pred[kind].unwrap()].0;
+    }
+    let mut cycle = Vec::new();
+    let mut at = kind;
+    loop {
+        let e = pred[at].unwrap();
+        cycle.push(edges[e].3);
+        at = edges[e].0;
+        if at == kind { break; }
+    }
+    cycle.reverse();
+    Some(cycle)
+}
+
+// Without any targets given only the amount of blue is printed, as a bare
+// number, and 0.0 if it is unreachable.
+struct Options {
+    bench: Option<u32>,
+    chain: bool,
+    arbitrage: bool,
+    exact: bool,
+    start: String,
+    targets: Vec<String>,
+    cap: Option<f64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            bench: None,
+            chain: false,
+            arbitrage: false,
+            exact: false,
+            start: String::from("pink"),
+            targets: Vec::new(),
+            cap: Some(10.0),
+        }
+    }
+}
+
+impl Options {
+    fn set(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            "start" => self.start = value.to_string(),
+            "target" => self.targets.extend(value.split(',').map(String::from)),
+            "cap" if value == "none" => self.cap = None,
+            "cap" => self.cap = Some(value.parse().ok().filter(|c: &f64| c.is_finite() && *c >= 0.0)?),
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: lemonade [--bench ROUNDS] [--chain] [--arbitrage] [--exact] [--start KIND] [--target KIND[,KIND...]]... [--cap AMOUNT|none] < input");
+    eprintln!("the trade count may be preceded by a header line like: start=pink target=blue,red cap=10");
+    eprintln!("a trade line may end in a volume, the most of the offered kind handed over");
+    process::exit(2);
+}
+
+// the header fills in what the command line left unset
+fn parse_args(header: &str) -> Options {
+    let mut opts = Options::default();
+    for item in header.split_ascii_whitespace() {
+        let mut split = item.splitn(2, '=');
+        let (key, value) = (split.next().unwrap(), split.next().unwrap_or_else(|| usage()));
+        opts.set(key, value).unwrap_or_else(|| usage());
+    }
+    let mut cli_targets = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--chain" => opts.chain = true,
+            "--arbitrage" => opts.arbitrage = true,
+            "--exact" => opts.exact = true,
+            "--bench" => {
+                let rounds = args.next().and_then(|r| r.parse().ok());
+                opts.bench = Some(rounds.unwrap_or_else(|| usage()));
+            }
+            "--start" | "--target" | "--cap" => {
+                if arg == "--target" && !cli_targets {
+                    opts.targets.clear();
+                    cli_targets = true;
+                }
+                let value = args.next().unwrap_or_else(|| usage());
+                opts.set(&arg[2..], &value).unwrap_or_else(|| usage());
+            }
+            _ => usage(),
+        }
+    }
+    opts
+}
+
+// In exact mode the exact amount follows the usual float output, and the
+// cap is applied by comparing exactly.
+fn format_amount<T: Amount>(amount: &T, opts: &Options) -> String {
+    let res = match opts.cap {
+        Some(cap) if amount.exceeds(cap) => format!("{:?}", cap),
+        _ => format!("{:.10}", amount.to_f64()),
+    };
+    if opts.exact {
+        format!("{} exact {}", res, amount.describe())
+    } else {
+        res
+    }
+}
+
+const EPS: f64 = 1e-9;
+
+// Trades that can matter for the target: their wanted kind can be held by
+// then, starting from the start kind, and what they offer can still be
+// traded on into the target by later trades.
+fn useful_trades(trades: &[Trade], start: &str, target: &str) -> Vec<usize> {
+    let mut held = HashSet::new();
+    held.insert(start);
+    let mut reachable = vec![false; trades.len()];
+    for (t, trade) in trades.iter().enumerate() {
+        if held.contains(trade.wanted.as_str()) {
+            reachable[t] = true;
+            held.insert(&trade.offered);
+        }
+    }
+    let mut leads = HashSet::new();
+    leads.insert(target);
+    let mut useful = Vec::new();
+    for (t, trade) in trades.iter().enumerate().rev() {
+        if reachable[t] && leads.contains(trade.offered.as_str()) {
+            leads.insert(&trade.wanted);
+            useful.push(t);
+        }
+    }
+    useful.reverse();
+    useful
+}
+
+// A network with gains: pushing f into an arc delivers f * gain at its
+// head. Arcs come in pairs, the reverse of arc e being e ^ 1, and cap is
+// what can still enter an arc.
+struct Flow {
+    adj: Vec<Vec<usize>>,
+    to: Vec<usize>,
+    cap: Vec<f64>,
+    gain: Vec<f64>,
+}
+
+impl Flow {
+    fn node(&mut self) -> usize {
+        self.adj.push(Vec::new());
+        self.adj.len() - 1
+    }
+    fn arc(&mut self, from: usize, to: usize, cap: f64, gain: f64) -> usize {
+        let e = self.to.len();
+        self.adj[from].push(e);
+        self.to.push(to);
+        self.cap.push(cap);
+        self.gain.push(gain);
+        self.adj[to].push(e + 1);
+        self.to.push(from);
+        self.cap.push(0.0);
+        self.gain.push(1.0 / gain);
+        e
+    }
+
+    // The residual path of highest gain, by Dijkstra over -log2(gain)
+    // made non-negative with the potentials of the previous search.
+    // Augmenting only along such paths never creates a cycle that gains,
+    // so the potentials stay valid, and nodes it can't reach stay that way.
+    // The search stops at the sink, and nodes not settled by then move up
+    // as if settled at the sink's distance, which keeps reduced costs
+    // non-negative.
+    fn best_path(&self, source: usize, sink: usize, potential: &mut [f64]) -> Option<Vec<usize>> {
+        let n = self.adj.len();
+        let mut dist = vec![f64::INFINITY; n];
+        let mut pred = vec![usize::MAX; n];
+        let mut queue = BinaryHeap::new();
+        dist[source] = 0.0;
+        // non-negative floats order like their bits
+        queue.push(Reverse((0f64.to_bits(), source)));
+        let mut settled = vec![false; n];
+        while let Some(Reverse((d, u))) = queue.pop() {
+            if settled[u] || f64::from_bits(d) > dist[u] { continue; }
+            settled[u] = true;
+            if u == sink { break; }
+            for &e in self.adj[u].iter() {
+                if self.cap[e] <= EPS { continue; }
+                let v = self.to[e];
+                let reduced = -self.gain[e].log2() + potential[u] - potential[v];
+                let d = dist[u] + reduced.max(0.0);
+                if d < dist[v] {
+                    dist[v] = d;
+                    pred[v] = e;
+                    queue.push(Reverse((d.to_bits(), v)));
+                }
+            }
+        }
+        if dist[sink].is_infinite() {
+            return None;
+        }
+        for v in 0..n {
+            potential[v] += if settled[v] { dist[v] } else { dist[sink] };
+        }
+        let mut path = Vec::new();
+        let mut v = sink;
+        while v != source {
+            let e = pred[v];
+            path.push(e);
+            v = self.to[e ^ 1];
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    // Augments along highest gain paths until none is left, returning what
+    // reaches the sink.
+    // Nodes are made in time order and every arc leads to a later node, so
+    // the first potentials come from a single pass.
+    fn maximize(&mut self, source: usize, sink: usize) -> f64 {
+        let mut potential = vec![f64::INFINITY; self.adj.len()];
+        potential[source] = 0.0;
+        for u in 0..self.adj.len() {
+            if potential[u].is_infinite() { continue; }
+            for &e in self.adj[u].iter().filter(|&&e| e % 2 == 0 && self.cap[e] > EPS) {
+                let v = self.to[e];
+                potential[v] = potential[v].min(potential[u] - self.gain[e].log2());
+            }
+        }
+        let mut total = 0.0;
+        while let Some(path) = self.best_path(source, sink, &mut potential) {
+            // the most that can leave the source, scaled back through the
+            // gains of each arc's prefix
+            let mut amount = f64::INFINITY;
+            let mut scale = 1.0;
+            for &e in path.iter() {
+                amount = amount.min(self.cap[e] / scale);
+                scale *= self.gain[e];
+            }
+            let mut flow = amount;
+            for &e in path.iter() {
+                self.cap[e] -= flow;
+                self.cap[e ^ 1] += flow * self.gain[e];
+                flow *= self.gain[e];
+            }
+            total += flow;
+        }
+        total
+    }
+}
+
+// With volumes the best amount may need to split over several paths. Each
+// kind's holdings become a chain of nodes through the trades, every trade
+// an arc with the rate as gain from its wanted kind's holdings to a new
+// node for its offered kind, and the result is a generalized maximum flow.
+// Only trades that can both be reached and lead on to the target are kept,
+// and each augmenting path saturates a volume, so the work grows with the
+// number of volumes that end up binding.
+// Returns the amount of the target and how much was given in each trade.
+fn solve_limited(trades: &[Trade], start: &str, target: &str) -> (f64, Vec<f64>) {
+    let mut flow = Flow { adj: Vec::new(), to: Vec::new(), cap: Vec::new(), gain: Vec::new() };
+    let source = flow.node();
+    let mut holding: HashMap<&str, usize> = HashMap::new();
+    let first = flow.node();
+    holding.insert(start, first);
+    flow.arc(source, first, 1.0, 1.0);
 
-                self.kinds.insert(to.to_string(), kind);
-                self.exchange.push(exchange);
+    let mut arcs = Vec::new();
+    for t in useful_trades(trades, start, target) {
+        let trade = &trades[t];
+        let rate: f64 = trade.rate.parse().unwrap();
+        let from = holding[trade.wanted.as_str()];
+        let next = flow.node();
+        if let Some(&prev) = holding.get(trade.offered.as_str()) {
+            flow.arc(prev, next, f64::INFINITY, 1.0);
+        }
+        let cap = trade.volume.map_or(f64::INFINITY, |v| v / rate);
+        arcs.push((t, flow.arc(from, next, cap, rate)));
+        holding.insert(&trade.offered, next);
+    }
+
+    let value = match holding.get(target) {
+        Some(&sink) => flow.maximize(source, sink),
+        None => 0.0,
+    };
+    let mut given = vec![0.0; trades.len()];
+    for (t, e) in arcs {
+        given[t] = flow.cap[e ^ 1] / flow.gain[e];
+    }
+    (value, given)
+}
+
+// Times the relaxation over the trades with bounds checked exchange access
+// against get_unchecked, the way Graph used to store it.
+fn bench(trades: &[Trade], start: &str, rounds: u32) {
+    let mut kinds = Kinds::new(start);
+    let edges: Vec<(Kind, Kind, f64)> = trades.iter().map(|trade| {
+        let rate: f64 = trade.rate.parse().unwrap();
+        (kinds.intern(&trade.wanted), kinds.intern(&trade.offered), rate.log2())
+    }).collect();
+    let (n, start) = (kinds.len(), kinds.start());
+
+    let time = |relax: &dyn Fn(&mut PerKind<f64>)| -> Duration {
+        let begin = Instant::now();
+        for _ in 0..rounds {
+            let mut exchange = PerKind(vec![f64::NEG_INFINITY; n]);
+            exchange[start] = 0.0;
+            relax(&mut exchange);
+            std::hint::black_box(&exchange);
+        }
+        begin.elapsed() / rounds.max(1)
+    };
+    let checked = time(&|exchange| {
+        for &(from, to, rate) in edges.iter() {
+            let exch = exchange[from] + rate;
+            if exch > exchange[to] {
+                exchange[to] = exch;
+            }
+        }
+    });
+    let unchecked = time(&|exchange| {
+        for &(from, to, rate) in edges.iter() {
+            unsafe {
+                let exch = *exchange.0.get_unchecked(from.0) + rate;
+                if exch > *exchange.0.get_unchecked(to.0) {
+                    *exchange.0.get_unchecked_mut(to.0) = exch;
+                }
+            }
+        }
+    });
+    println!("{} trades, {} kinds, {} rounds", trades.len(), n, rounds);
+    println!("checked {:?} per round", checked);
+    println!("unchecked {:?} per round", unchecked);
+}
+
+// Reachability still comes from the unlimited graph, only the amounts and
+// the chain, now the amounts given and received in each trade used, differ.
+fn solve_volumes(trades: &[Trade], opts: &Options) {
+    let mut graph = Graph::<f64>::new(&opts.start);
+    for (i, trade) in trades.iter().enumerate() {
+        graph.add_edge(&trade.wanted, &trade.offered, &f64::parse_rate(&trade.rate), i);
+    }
+
+    let amount = |target: &str| -> String {
+        let (value, x) = solve_limited(trades, &opts.start, target);
+        if opts.chain {
+            for (trade, &given) in trades.iter().zip(x.iter()) {
+                if given <= EPS { continue; }
+                let received = given * trade.rate.parse::<f64>().unwrap();
+                println!("{} {} {} {:.10} {:.10}", trade.offered, trade.wanted, trade.rate, given, received);
+            }
+        }
+        // amounts as f64 are kept as logarithms
+        format_amount(&value.max(0.0).log2(), opts)
+    };
+
+    if opts.targets.is_empty() {
+        match graph.kinds.get("blue") {
+            Some(_) => println!("{}", amount("blue")),
+            None => println!("0.0"),
+        }
+        return;
+    }
+
+    for target in opts.targets.iter() {
+        match graph.kinds.get(target.as_str()) {
+            Some(_) => println!("{} {}", target, amount(target)),
+            None => println!("{} unreachable", target),
+        }
+    }
+}
+
+fn solve<T: Amount>(trades: &[Trade], opts: &Options) {
+    let mut graph = Graph::<T>::new(&opts.start);
+    for (i, trade) in trades.iter().enumerate() {
+        graph.add_edge(&trade.wanted, &trade.offered, &T::parse_rate(&trade.rate), i);
+    }
+    if cfg!(debug_assertions) {
+        graph.check();
+    }
+
+    let print_chain = |kind: Kind| {
+        let mut amount = T::one();
+        for i in graph.chain(kind) {
+            let trade = &trades[i];
+            amount = amount.times(&T::parse_rate(&trade.rate));
+            println!("{} {} {} {}", trade.offered, trade.wanted, trade.rate, amount.describe());
+        }
+    };
+
+    if opts.targets.is_empty() {
+        let blue_kind = match graph.kinds.get("blue") {
+            Some(bk) => bk,
+            None => {
+                println!("0.0");
+                return;
             }
+        };
+        if opts.chain {
+            print_chain(blue_kind);
         }
+        println!("{}", format_amount(graph.get_exch(blue_kind), opts));
+        return;
+    }
 
+    for target in opts.targets.iter() {
+        match graph.kinds.get(target.as_str()) {
+            Some(kind) => {
+                if opts.chain {
+                    print_chain(kind);
+                }
+                println!("{} {}", target, format_amount(graph.get_exch(kind), opts));
+            }
+            None => println!("{} unreachable", target),
+        }
     }
 }
 
@@ -67,9 +845,16 @@ fn main() {
 
     let mut buf = String::new();
     stdin.read_line(&mut buf).unwrap();
+    let mut header = String::new();
+    if buf.trim().parse::<usize>().is_err() {
+        header = buf.clone();
+        buf.clear();
+        stdin.read_line(&mut buf).unwrap();
+    }
+    let opts = parse_args(&header);
     let n = buf.trim().parse().unwrap();
 
-    let mut graph = Graph::new(n);
+    let mut trades = Vec::with_capacity(n);
 
     for _ in 0..n {
         buf.clear();
@@ -78,22 +863,53 @@ fn main() {
 
         let offered = split.next().unwrap();
         let wanted = split.next().unwrap();
-        let exchange: f64 = split.next().unwrap().parse().unwrap();
+        let rate = split.next().unwrap();
+        let volume = split.next().map(|v| v.parse::<f64>().ok().filter(|v| *v >= 0.0).unwrap_or_else(|| {
+            eprintln!("lemonade: {} is not a trade volume", v);
+            process::exit(1);
+        }));
+        if opts.exact && Decimal::parse(rate).is_none() {
+            eprintln!("lemonade: {} is not a decimal rate", rate);
+            process::exit(1);
+        }
+
+        trades.push(Trade {
+            offered: offered.to_string(),
+            wanted: wanted.to_string(),
+            rate: rate.to_string(),
+            volume,
+        });
+    }
 
-        graph.add_edge(wanted, offered, exchange.log2());
+    if let Some(rounds) = opts.bench {
+        bench(&trades, &opts.start, rounds);
+        return;
     }
 
-    let blue_kind = match graph.kinds.get("blue") {
-        Some(bk) => bk,
-        None => {
-            println!("0.0");
-            return;
+    if opts.arbitrage {
+        match find_arbitrage(&trades) {
+            Some(cycle) => {
+                let gain: f64 = cycle.iter().map(|&i| trades[i].rate.parse::<f64>().unwrap()).product();
+                println!("gain {:.10}", gain);
+                for i in cycle {
+                    let trade = &trades[i];
+                    println!("{} {} {}", trade.offered, trade.wanted, trade.rate);
+                }
+            }
+            None => println!("no arbitrage"),
         }
-    };
-    let blue = graph.get_exch(*blue_kind).exp2();
-    if blue > 10.0 {
-        println!("10.0");
+        return;
+    }
+
+    if trades.iter().any(|trade| trade.volume.is_some()) {
+        if opts.exact {
+            eprintln!("lemonade: --exact does not support trade volumes");
+            process::exit(1);
+        }
+        solve_volumes(&trades, &opts);
+    } else if opts.exact {
+        solve::<Decimal>(&trades, &opts);
     } else {
-        println!("{:.10}", blue);
+        solve::<f64>(&trades, &opts);
     }
 }